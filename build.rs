@@ -17,4 +17,12 @@ fn main() {
 
     let date = Local::now();
     println!("cargo:rustc-env=BUILD_DATE={}", date.format("%Y-%m-%d"));
+
+    // IODisplayCreateInfoDictionary, used to read EDIDs, lives in IOKit
+    if std::env::var("CARGO_CFG_TARGET_OS")
+        .map(|os| os == "macos")
+        .unwrap_or(false)
+    {
+        println!("cargo:rustc-link-lib=framework=IOKit");
+    }
 }
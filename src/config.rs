@@ -0,0 +1,332 @@
+use toml;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use errors::*;
+use hooks::Hooks;
+use paths;
+
+pub const DEFAULT_VERIFY_RETRIES: u32 = 3;
+pub const DEFAULT_VERIFY_DELAY: u64 = 500;
+
+/// User configuration, read from `config.toml` (see `paths::config_file`):
+///
+/// ```toml
+/// [defaults]
+/// format = "long"
+/// display = "left"
+///
+/// [set]
+/// verify = true
+/// idempotent = true
+/// exact = false
+/// force = false
+///
+/// [aliases]
+/// left = "1"
+/// tv = "10ac:a0c4"
+///
+/// [favorites]
+/// work = "1440x900x32@0"
+///
+/// [hooks]
+/// pre = "osascript -e 'quit app \"Fragile\"'"
+/// post = "/usr/local/bin/reposition-windows"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: Defaults,
+    #[serde(default)]
+    pub set: SetDefaults,
+    /// Display names, to a display index or a display identity prefix (or `builtin`).
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    /// Mode names, to a mode string.
+    #[serde(default)]
+    pub favorites: BTreeMap<String, String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Short,
+    Long,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Defaults {
+    /// Output format of `list` and `get`.
+    pub format: Option<OutputFormat>,
+    /// Display used by commands that change a mode, as an index or an alias.
+    pub display: Option<String>,
+}
+
+/// Defaults for the options of `set`.
+#[derive(Debug, Default, Deserialize)]
+pub struct SetDefaults {
+    pub verify: Option<bool>,
+    pub verify_retries: Option<u32>,
+    pub verify_delay: Option<u64>,
+    pub idempotent: Option<bool>,
+    pub exact: Option<bool>,
+    pub force: Option<bool>,
+}
+
+impl Config {
+    pub fn parse(config: &str) -> Result<Config> {
+        toml::from_str(config).chain_err(|| "Not a valid configuration file")
+    }
+
+    pub fn load(path: &Path) -> Result<Config> {
+        let mut config = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut config))
+            .chain_err(|| format!("Could not read configuration file: {}", path.display()))?;
+        Config::parse(&config)
+    }
+
+    /// The user configuration, or the default one when there is no configuration file.
+    pub fn open() -> Result<Config> {
+        let path = paths::config_file()?;
+        if path.exists() {
+            Config::load(&path)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.defaults.format.unwrap_or(OutputFormat::Short)
+    }
+
+    pub fn display(&self) -> &str {
+        self.defaults
+            .display
+            .as_ref()
+            .map_or("0", |display| display.as_str())
+    }
+
+    pub fn verify(&self) -> bool {
+        self.set.verify.unwrap_or(false)
+    }
+
+    pub fn verify_retries(&self) -> u32 {
+        self.set.verify_retries.unwrap_or(DEFAULT_VERIFY_RETRIES)
+    }
+
+    pub fn verify_delay(&self) -> u64 {
+        self.set.verify_delay.unwrap_or(DEFAULT_VERIFY_DELAY)
+    }
+
+    pub fn idempotent(&self) -> bool {
+        self.set.idempotent.unwrap_or(false)
+    }
+
+    pub fn exact(&self) -> bool {
+        self.set.exact.unwrap_or(false)
+    }
+
+    pub fn force(&self) -> bool {
+        self.set.force.unwrap_or(false)
+    }
+
+    /// What the display alias stands for, or the display itself when it is not an alias.
+    pub fn resolve_display<'a>(&'a self, display: &'a str) -> &'a str {
+        self.aliases
+            .get(display)
+            .map_or(display, |target| target.as_str())
+    }
+
+    /// The mode of the favorite, or the mode itself when it is not a favorite.
+    pub fn resolve_mode<'a>(&'a self, mode: &'a str) -> &'a str {
+        self.favorites.get(mode).map_or(mode, |mode| mode.as_str())
+    }
+
+    /// Print the effective settings, built-in defaults included, in the configuration file format.
+    pub fn print_config(&self, output: &mut io::Write) -> Result<()> {
+        writeln!(output, "[defaults]")?;
+        writeln!(
+            output,
+            "format = {}",
+            toml_string(match self.format() {
+                OutputFormat::Short => "short",
+                OutputFormat::Long => "long",
+            })
+        )?;
+        writeln!(output, "display = {}", toml_string(self.display()))?;
+        writeln!(output, "")?;
+        writeln!(output, "[set]")?;
+        writeln!(output, "verify = {}", self.verify())?;
+        writeln!(output, "verify_retries = {}", self.verify_retries())?;
+        writeln!(output, "verify_delay = {}", self.verify_delay())?;
+        writeln!(output, "idempotent = {}", self.idempotent())?;
+        writeln!(output, "exact = {}", self.exact())?;
+        writeln!(output, "force = {}", self.force())?;
+        writeln!(output, "")?;
+        writeln!(output, "[aliases]")?;
+        for (alias, display) in self.aliases.iter() {
+            writeln!(output, "{} = {}", toml_key(alias), toml_string(display))?;
+        }
+        writeln!(output, "")?;
+        writeln!(output, "[favorites]")?;
+        for (name, mode) in self.favorites.iter() {
+            writeln!(output, "{} = {}", toml_key(name), toml_string(mode))?;
+        }
+        writeln!(output, "")?;
+        writeln!(output, "[hooks]")?;
+        if let Some(ref pre) = self.hooks.pre {
+            writeln!(output, "pre = {}", toml_string(pre))?;
+        }
+        if let Some(ref post) = self.hooks.post {
+            writeln!(output, "post = {}", toml_string(post))?;
+        }
+        Ok(())
+    }
+}
+
+/// A TOML string holding the value.
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+/// A TOML key: bare when it can be, quoted otherwise.
+fn toml_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[defaults]
+format = "long"
+display = "left"
+
+[set]
+verify = true
+force = true
+
+[aliases]
+left = "1"
+tv = "10ac:a0c4"
+
+[favorites]
+work = "1440x900x32@0"
+
+[hooks]
+pre = "true"
+"#;
+
+    #[test]
+    fn parse_hooks() {
+        let config = Config::parse("[hooks]\npre = \"true\"\n").unwrap();
+
+        assert_eq!(Some("true".to_string()), config.hooks.pre);
+        assert_eq!(None, config.hooks.post);
+    }
+
+    #[test]
+    fn parse_empty() {
+        let config = Config::parse("").unwrap();
+
+        assert_eq!(true, config.hooks.is_empty());
+        assert_eq!(OutputFormat::Short, config.format());
+        assert_eq!("0", config.display());
+        assert_eq!(false, config.verify());
+        assert_eq!(DEFAULT_VERIFY_RETRIES, config.verify_retries());
+        assert_eq!(false, config.exact());
+        assert_eq!(false, config.force());
+    }
+
+    #[test]
+    fn parse_invalid_format() {
+        assert_eq!(
+            true,
+            Config::parse("[defaults]\nformat = \"wide\"\n").is_err()
+        );
+    }
+
+    #[test]
+    fn resolve_aliases_and_favorites() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        assert_eq!("1", config.resolve_display(config.display()));
+        assert_eq!("10ac:a0c4", config.resolve_display("tv"));
+        assert_eq!("2", config.resolve_display("2"));
+        assert_eq!("1440x900x32@0", config.resolve_mode("work"));
+        assert_eq!("800x600x32@0", config.resolve_mode("800x600x32@0"));
+    }
+
+    #[test]
+    fn print_config() {
+        let config = Config::parse(CONFIG).unwrap();
+        let mut vec = Vec::<u8>::new();
+
+        config
+            .print_config(&mut vec)
+            .expect("Error while testing print_config");
+
+        assert_eq!(
+            "[defaults]\n\
+             format = \"long\"\n\
+             display = \"left\"\n\
+             \n\
+             [set]\n\
+             verify = true\n\
+             verify_retries = 3\n\
+             verify_delay = 500\n\
+             idempotent = false\n\
+             exact = false\n\
+             force = true\n\
+             \n\
+             [aliases]\n\
+             left = \"1\"\n\
+             tv = \"10ac:a0c4\"\n\
+             \n\
+             [favorites]\n\
+             work = \"1440x900x32@0\"\n\
+             \n\
+             [hooks]\n\
+             pre = \"true\"\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn print_config_escapes_toml() {
+        let mut config = Config::parse(CONFIG).unwrap();
+        config
+            .favorites
+            .insert("dark room".to_string(), "1280x720x32@60".to_string());
+        config.hooks.post = Some("say \"done\" \\ \u{e9}\t".to_string());
+        let mut vec = Vec::<u8>::new();
+
+        config
+            .print_config(&mut vec)
+            .expect("Error while testing print_config");
+        let printed = String::from_utf8(vec).unwrap();
+
+        assert_eq!(
+            true,
+            printed.contains("\"dark room\" = \"1280x720x32@60\"\n")
+        );
+        let reparsed = Config::parse(&printed).unwrap();
+        assert_eq!(config.favorites, reparsed.favorites);
+        assert_eq!(config.hooks.post, reparsed.hooks.post);
+    }
+}
@@ -0,0 +1,32 @@
+use core_graphics::base;
+use core_graphics::display::{CGConfigureOption, CGDisplay, CGDisplayConfigRef};
+
+use errors::*;
+
+/// Make changes to the displays in a single display configuration: it is completed permanently
+/// once `configure` succeeds and cancelled when it fails, so that either all the changes are
+/// applied or none.
+pub fn with_configuration<F>(display: &CGDisplay, configure: F) -> Result<()>
+where
+    F: FnOnce(CGDisplayConfigRef) -> Result<()>,
+{
+    let config_ref = display
+        .begin_configuration()
+        .map_err(|e| ErrorKind::BackendError(e.into()))?;
+    if let Err(e) = configure(config_ref) {
+        let _ = display.cancel_configuration(&config_ref);
+        return Err(e);
+    }
+    display
+        .complete_configuration(&config_ref, CGConfigureOption::ConfigurePermanently)
+        .map_err(|e| ErrorKind::ConfigurationRejected(e.into()))?;
+    Ok(())
+}
+
+/// The result of a change made in a display configuration, from its CoreGraphics error code.
+pub fn configured(error: base::CGError) -> Result<()> {
+    match error {
+        0 => Ok(()),
+        error => Err(ErrorKind::ConfigurationRejected(error.into()).into()),
+    }
+}
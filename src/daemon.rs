@@ -0,0 +1,363 @@
+use libc;
+use toml;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use errors::*;
+use events::*;
+use mode::DisplayIndex;
+
+/// Rules file, in TOML:
+///
+/// ```toml
+/// [[rule]]
+/// name = "desk"
+///
+/// [[rule.display]]
+/// identity = "10ac:a0c4"
+/// mode = "2560x1440x32@60"
+///
+/// [[rule.display]]
+/// identity = "builtin"
+/// mode = "1440x900x32@0"
+/// position = "560,1440"
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Rules {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+/// Modes to apply when exactly the listed displays are connected.
+#[derive(Debug, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default, rename = "display")]
+    pub displays: Vec<RuleDisplay>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuleDisplay {
+    /// Prefix of a display identity (`vendor:model:serial`), or `builtin`.
+    pub identity: String,
+    pub mode: String,
+    /// Origin of the display in the desktop, like `1920,0`, see `layout::Origin`.
+    #[serde(default)]
+    pub position: Option<String>,
+}
+
+/// A display as seen when looking for a matching rule.
+pub struct ConnectedDisplay {
+    pub index: DisplayIndex,
+    pub identity: String,
+    pub builtin: bool,
+}
+
+impl RuleDisplay {
+    fn matches(&self, display: &ConnectedDisplay) -> bool {
+        if self.identity == "builtin" {
+            display.builtin
+        } else {
+            display.identity.starts_with(self.identity.as_str())
+        }
+    }
+}
+
+impl Rule {
+    /// The rule display to apply to each display when the rule matches the connected displays.
+    pub fn assign(
+        &self,
+        connected: &[ConnectedDisplay],
+    ) -> Option<Vec<(DisplayIndex, &RuleDisplay)>> {
+        if self.displays.len() != connected.len() {
+            return None;
+        }
+        let mut assignments: Vec<(DisplayIndex, &RuleDisplay)> = Vec::new();
+        if self.assign_from(0, connected, &mut assignments) {
+            Some(assignments)
+        } else {
+            None
+        }
+    }
+
+    /// Give a display to each rule display from the `next` one, trying the other matching
+    /// displays when the rest cannot be assigned: a broad identity must not take the display a
+    /// later, more specific one needs.
+    fn assign_from<'a>(
+        &'a self,
+        next: usize,
+        connected: &[ConnectedDisplay],
+        assignments: &mut Vec<(DisplayIndex, &'a RuleDisplay)>,
+    ) -> bool {
+        let rule_display = match self.displays.get(next) {
+            Some(rule_display) => rule_display,
+            None => return true,
+        };
+        for display in connected {
+            if rule_display.matches(display)
+                && !assignments.iter().any(|&(index, _)| index == display.index)
+            {
+                assignments.push((display.index, rule_display));
+                if self.assign_from(next + 1, connected, assignments) {
+                    return true;
+                }
+                assignments.pop();
+            }
+        }
+        false
+    }
+}
+
+impl Rules {
+    pub fn parse(rules: &str) -> Result<Rules> {
+        toml::from_str(rules).chain_err(|| "Not a valid rules file")
+    }
+
+    pub fn load(path: &Path) -> Result<Rules> {
+        let mut rules = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut rules))
+            .chain_err(|| format!("Could not read rules file: {}", path.display()))?;
+        Rules::parse(&rules)
+    }
+
+    /// The first rule matching the connected displays, with what to apply to each display.
+    pub fn find_match(
+        &self,
+        connected: &[ConnectedDisplay],
+    ) -> Option<(&Rule, Vec<(DisplayIndex, &RuleDisplay)>)> {
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                rule.assign(connected)
+                    .map(|assignments| (rule, assignments))
+            }).next()
+    }
+}
+
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sighup(_: libc::c_int) {
+    RELOAD.store(true, Ordering::SeqCst);
+}
+
+/// Ask for the rules to be reloaded when the process receives SIGHUP.
+pub fn reload_on_sighup() {
+    unsafe {
+        libc::signal(
+            libc::SIGHUP,
+            on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Apply the rules once at start, then again after every burst of display events, that is once
+/// no event came for `debounce`, and after the rules were reloaded. Errors while applying are
+/// reported and do not stop the daemon.
+pub fn run<F>(
+    source: &mut DisplayEventSource,
+    debounce: Duration,
+    rules_path: &Path,
+    output: &mut io::Write,
+    mut apply: F,
+) -> Result<()>
+where
+    F: FnMut(&Rules, &mut io::Write) -> Result<()>,
+{
+    let mut rules = Rules::load(rules_path)?;
+    let mut changed = true;
+    loop {
+        if changed {
+            if let Err(e) = apply(&rules, output) {
+                writeln!(output, "Error: {}", e)?;
+            }
+            changed = false;
+        }
+        if source.is_exhausted() {
+            return Ok(());
+        }
+        if let Some(event) = source.next_event(Some(Duration::from_secs(1)))? {
+            event.print_event(false, output)?;
+            writeln!(output, "")?;
+            while let Some(event) = source.next_event(Some(debounce))? {
+                event.print_event(false, output)?;
+                writeln!(output, "")?;
+            }
+            changed = true;
+        }
+        if RELOAD.swap(false, Ordering::SeqCst) {
+            match Rules::load(rules_path) {
+                Ok(reloaded) => {
+                    writeln!(output, "Rules reloaded")?;
+                    rules = reloaded;
+                    changed = true;
+                }
+                Err(e) => writeln!(output, "Error: {}, keeping previous rules", e)?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    const RULES: &str = r#"
+[[rule]]
+name = "desk"
+
+[[rule.display]]
+identity = "10ac:a0c4"
+mode = "2560x1440x32@60"
+
+[[rule.display]]
+identity = "builtin"
+mode = "1440x900x32@0"
+position = "560,1440"
+
+[[rule]]
+name = "laptop"
+
+[[rule.display]]
+identity = "builtin"
+mode = "1680x1050x32@0"
+"#;
+
+    fn connected_display(index: DisplayIndex, identity: &str, builtin: bool) -> ConnectedDisplay {
+        ConnectedDisplay {
+            index,
+            identity: identity.to_string(),
+            builtin,
+        }
+    }
+
+    #[test]
+    fn find_match_desk() {
+        let rules = Rules::parse(RULES).unwrap();
+        let connected = vec![
+            connected_display(0, "0610:a050:00000000", true),
+            connected_display(1, "10ac:a0c4:4c4a3035", false),
+        ];
+
+        let (rule, assignments) = rules.find_match(&connected).unwrap();
+        let assignments: Vec<(DisplayIndex, &str, Option<&str>)> = assignments
+            .iter()
+            .map(|&(index, display)| {
+                (
+                    index,
+                    display.mode.as_str(),
+                    display.position.as_ref().map(|position| position.as_str()),
+                )
+            }).collect();
+
+        assert_eq!("desk", rule.name);
+        assert_eq!(
+            vec![
+                (1, "2560x1440x32@60", None),
+                (0, "1440x900x32@0", Some("560,1440")),
+            ],
+            assignments
+        );
+    }
+
+    #[test]
+    fn find_match_laptop() {
+        let rules = Rules::parse(RULES).unwrap();
+        let connected = vec![connected_display(0, "0610:a050:00000000", true)];
+
+        let (rule, _) = rules.find_match(&connected).unwrap();
+
+        assert_eq!("laptop", rule.name);
+    }
+
+    #[test]
+    fn find_match_overlapping_identities() {
+        let rules = Rules::parse(
+            r#"
+[[rule]]
+name = "two dells"
+
+[[rule.display]]
+identity = "10ac"
+mode = "1920x1080x32@60"
+
+[[rule.display]]
+identity = "10ac:a0c4"
+mode = "2560x1440x32@60"
+"#,
+        ).unwrap();
+        let connected = vec![
+            connected_display(0, "10ac:a0c4:4c4a3035", false),
+            connected_display(1, "10ac:d0b3:00000000", false),
+        ];
+
+        let (_, assignments) = rules.find_match(&connected).unwrap();
+        let assignments: Vec<(DisplayIndex, &str)> = assignments
+            .iter()
+            .map(|&(index, display)| (index, display.mode.as_str()))
+            .collect();
+
+        assert_eq!(
+            vec![(1, "1920x1080x32@60"), (0, "2560x1440x32@60")],
+            assignments
+        );
+    }
+
+    #[test]
+    fn find_match_none() {
+        let rules = Rules::parse(RULES).unwrap();
+        let connected = vec![
+            connected_display(0, "0610:a050:00000000", true),
+            connected_display(1, "1e6d:5b11:0001e3c5", false),
+        ];
+
+        assert_eq!(true, rules.find_match(&connected).is_none());
+    }
+
+    #[test]
+    fn run_debounces_events() {
+        let path = env::temp_dir().join(format!(
+            "screenresolution-rs-rules-{}.toml",
+            ::std::process::id()
+        ));
+        File::create(&path)
+            .unwrap()
+            .write_all(RULES.as_bytes())
+            .unwrap();
+        let mut source = MemoryEventSource::new(vec![
+            DisplayEvent::Connected(2),
+            DisplayEvent::ModeChanged(2, "1920x1200x32@60".to_string()),
+            DisplayEvent::BecameMain(2),
+        ]);
+        let mut vec = Vec::<u8>::new();
+        let mut applied = 0;
+
+        run(
+            &mut source,
+            Duration::from_millis(0),
+            &path,
+            &mut vec,
+            |rules, _| {
+                assert_eq!(2, rules.rules.len());
+                applied += 1;
+                Ok(())
+            },
+        ).expect("Error while testing run");
+
+        fs::remove_file(path).unwrap();
+        assert_eq!(2, applied);
+        assert_eq!(
+            "Display 2 connected\n\
+             Display 2 mode changed to 1920x1200x32@60\n\
+             Display 2 became main\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+}
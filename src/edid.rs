@@ -0,0 +1,382 @@
+use core_foundation::base::{CFType, TCFType};
+use core_foundation::data::CFData;
+use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+use core_foundation::string::CFString;
+use core_graphics::display::CGDirectDisplayID;
+
+use encoding::{ColorFormat, EncodingCapabilities};
+use errors::*;
+use refresh::RefreshRate;
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const BLOCK_LENGTH: usize = 128;
+const DESCRIPTORS: [usize; 4] = [54, 72, 90, 108];
+const DESCRIPTOR_LENGTH: usize = 18;
+const RANGE_LIMITS_TAG: u8 = 0xfd;
+/// Feature support bit: continuous frequency in EDID 1.4, default GTF in earlier versions.
+const CONTINUOUS_FREQUENCY: u8 = 0x01;
+/// Video input definition bit of digital displays.
+const DIGITAL_INPUT: u8 = 0x80;
+const CTA_EXTENSION_TAG: u8 = 0x02;
+const CTA_YCBCR444: u8 = 0x20;
+const CTA_YCBCR422: u8 = 0x10;
+const CTA_EXTENDED_TAG: u8 = 7;
+const CTA_HDR_STATIC_METADATA: u8 = 0x06;
+const CTA_YCBCR420_VIDEO: u8 = 0x0e;
+const CTA_YCBCR420_CAPABILITY_MAP: u8 = 0x0f;
+/// Transfer functions of the HDR static metadata block beyond traditional SDR: traditional HDR,
+/// SMPTE ST 2084 (PQ) and HLG.
+const HDR_EOTFS: u8 = 0x0e;
+/// Smallest range, in hertz, taken as adaptive sync rather than as the usual tolerance of a
+/// fixed rate display.
+const MIN_VRR_SPAN: u32 = 10;
+
+/// The monitor range limits descriptor of an EDID.
+#[derive(Debug, PartialEq)]
+pub struct RangeLimits {
+    pub min_vertical_hz: u32,
+    pub max_vertical_hz: u32,
+    pub min_horizontal_khz: u32,
+    pub max_horizontal_khz: u32,
+    pub max_pixel_clock_mhz: u32,
+}
+
+/// What the tool uses of the base block of an EDID.
+#[derive(Debug, PartialEq)]
+pub struct Edid {
+    pub version: u8,
+    pub revision: u8,
+    pub continuous_frequency: bool,
+    pub range_limits: Option<RangeLimits>,
+    /// Color depth of a digital EDID 1.4 display.
+    pub bits_per_component: Option<u8>,
+    /// Color formats the display takes, from the base block and the CTA-861 extensions.
+    pub color_formats: Vec<ColorFormat>,
+    /// Whether a CTA-861 HDR static metadata block lists an HDR transfer function.
+    pub hdr: bool,
+    /// Width and height of the image in millimeters.
+    pub size_mm: Option<(u32, u32)>,
+}
+
+impl Edid {
+    pub fn parse(edid: &[u8]) -> Result<Edid> {
+        if edid.len() < BLOCK_LENGTH || edid[..HEADER.len()] != HEADER {
+            return Err("Not an EDID".into());
+        }
+        if checksum(&edid[..BLOCK_LENGTH]) != 0 {
+            return Err("EDID checksum does not match".into());
+        }
+        let version = (edid[18], edid[19]);
+        let digital_1_4 = version >= (1, 4) && edid[20] & DIGITAL_INPUT != 0;
+        let mut color_formats = vec![ColorFormat::Rgb];
+        if digital_1_4 {
+            // Bits 4 and 3 of the feature support byte: YCbCr 4:4:4, then YCbCr 4:2:2
+            if edid[24] & 0x08 != 0 {
+                color_formats.push(ColorFormat::YCbCr444);
+            }
+            if edid[24] & 0x10 != 0 {
+                color_formats.push(ColorFormat::YCbCr422);
+            }
+        }
+        let mut hdr = false;
+        for block in edid[BLOCK_LENGTH..].chunks(BLOCK_LENGTH).filter(|block| {
+            block.len() == BLOCK_LENGTH && block[0] == CTA_EXTENSION_TAG && checksum(block) == 0
+        }) {
+            if block[3] & CTA_YCBCR444 != 0 {
+                color_formats.push(ColorFormat::YCbCr444);
+            }
+            if block[3] & CTA_YCBCR422 != 0 {
+                color_formats.push(ColorFormat::YCbCr422);
+            }
+            // Data blocks run from byte 4 to the first detailed timing descriptor
+            let end = (block[2] as usize).min(BLOCK_LENGTH - 1);
+            let mut offset = 4;
+            while offset < end {
+                let tag = block[offset] >> 5;
+                let length = (block[offset] & 0x1f) as usize;
+                if tag == CTA_EXTENDED_TAG && length >= 1 && offset + 1 < end {
+                    match block[offset + 1] {
+                        CTA_HDR_STATIC_METADATA if length >= 2 => {
+                            hdr |= block[offset + 2] & HDR_EOTFS != 0;
+                        }
+                        CTA_YCBCR420_VIDEO | CTA_YCBCR420_CAPABILITY_MAP => {
+                            color_formats.push(ColorFormat::YCbCr420);
+                        }
+                        _ => {}
+                    }
+                }
+                offset += 1 + length;
+            }
+        }
+        let mut deduplicated: Vec<ColorFormat> = Vec::new();
+        for format in color_formats {
+            if !deduplicated.contains(&format) {
+                deduplicated.push(format);
+            }
+        }
+        Ok(Edid {
+            version: edid[18],
+            revision: edid[19],
+            continuous_frequency: edid[24] & CONTINUOUS_FREQUENCY != 0,
+            range_limits: DESCRIPTORS
+                .iter()
+                .map(|&offset| &edid[offset..offset + DESCRIPTOR_LENGTH])
+                .find(|descriptor| {
+                    descriptor[..3] == [0, 0, 0] && descriptor[3] == RANGE_LIMITS_TAG
+                }).map(range_limits),
+            bits_per_component: if digital_1_4 {
+                match (edid[20] >> 4) & 0x07 {
+                    depth @ 1..=6 => Some(4 + 2 * depth),
+                    _ => None,
+                }
+            } else {
+                None
+            },
+            color_formats: deduplicated,
+            hdr,
+            size_mm: size_mm(edid),
+        })
+    }
+
+    /// The encodings the display takes. Displays that do not tell their color depth are taken as
+    /// 8 bits per component.
+    pub fn encoding_capabilities(&self) -> EncodingCapabilities {
+        EncodingCapabilities {
+            max_bits_per_component: self.bits_per_component.unwrap_or(8),
+            formats: self.color_formats.clone(),
+            hdr: self.hdr,
+        }
+    }
+
+    /// The refresh rate range the display can follow, as a `RefreshRate::Variable`. Like the
+    /// Linux DRM drivers, a display is taken as adaptive sync capable when it is EDID 1.4 with
+    /// continuous frequency support and a vertical range wider than 10 Hz.
+    pub fn vrr_range(&self) -> Option<RefreshRate> {
+        if (self.version, self.revision) < (1, 4) || !self.continuous_frequency {
+            return None;
+        }
+        self.range_limits
+            .as_ref()
+            .filter(|limits| limits.max_vertical_hz > limits.min_vertical_hz + MIN_VRR_SPAN)
+            .map(|limits| RefreshRate::Variable {
+                min: limits.min_vertical_hz * 1000,
+                max: limits.max_vertical_hz * 1000,
+            })
+    }
+}
+
+/// Blocks are valid when all their bytes add up to 0.
+fn checksum(block: &[u8]) -> u8 {
+    block.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// The image size of the first detailed timing descriptor, in millimeters, or else the screen
+/// size of the basic display parameters, in centimeters. Sizes of 0 stand for unknown.
+fn size_mm(edid: &[u8]) -> Option<(u32, u32)> {
+    let timing = &edid[DESCRIPTORS[0]..DESCRIPTORS[0] + DESCRIPTOR_LENGTH];
+    // Detailed timing descriptors start with a pixel clock, other descriptors with 0
+    let detailed = if timing[0] != 0 || timing[1] != 0 {
+        Some((
+            timing[12] as u32 | (timing[14] as u32 >> 4) << 8,
+            timing[13] as u32 | (timing[14] as u32 & 0x0f) << 8,
+        ))
+    } else {
+        None
+    };
+    let basic = Some((edid[21] as u32 * 10, edid[22] as u32 * 10));
+    detailed
+        .into_iter()
+        .chain(basic)
+        .find(|&(width, height)| width > 0 && height > 0)
+}
+
+fn range_limits(descriptor: &[u8]) -> RangeLimits {
+    // EDID 1.4 adds 255 to a rate when its offset bit is set
+    let offsets = descriptor[4];
+    let rate = |byte: u8, offset_mask: u8| {
+        let offset = if offsets & offset_mask == offset_mask {
+            255
+        } else {
+            0
+        };
+        byte as u32 + offset
+    };
+    RangeLimits {
+        min_vertical_hz: rate(descriptor[5], 0x03),
+        max_vertical_hz: rate(descriptor[6], 0x02),
+        min_horizontal_khz: rate(descriptor[7], 0x0c),
+        max_horizontal_khz: rate(descriptor[8], 0x08),
+        max_pixel_clock_mhz: descriptor[9] as u32 * 10,
+    }
+}
+
+#[allow(non_upper_case_globals)]
+const kIODisplayOnlyPreferredName: u32 = 0x0000_0200;
+
+extern "C" {
+    fn CGDisplayIOServicePort(display: CGDirectDisplayID) -> u32;
+    fn IODisplayCreateInfoDictionary(framebuffer: u32, options: u32) -> CFDictionaryRef;
+}
+
+/// The EDID of the display, when IOKit knows it.
+pub fn read_edid(display_id: CGDirectDisplayID) -> Option<Vec<u8>> {
+    let info: CFDictionary<CFType, CFType> = unsafe {
+        let framebuffer = CGDisplayIOServicePort(display_id);
+        if framebuffer == 0 {
+            return None;
+        }
+        let info = IODisplayCreateInfoDictionary(framebuffer, kIODisplayOnlyPreferredName);
+        if info.is_null() {
+            return None;
+        }
+        CFDictionary::wrap_under_create_rule(info)
+    };
+    let key = CFString::from_static_string("IODisplayEDID").as_CFType();
+    info.find(&key)
+        .and_then(|edid| edid.downcast::<CFData>())
+        .map(|edid| edid.bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An EDID base block with a range limits descriptor and a valid checksum.
+    fn test_edid(revision: u8, features: u8, offsets: u8, min_hz: u8, max_hz: u8) -> Vec<u8> {
+        let mut edid = vec![0u8; BLOCK_LENGTH];
+        edid[..HEADER.len()].copy_from_slice(&HEADER);
+        edid[18] = 1;
+        edid[19] = revision;
+        edid[24] = features;
+        edid[72..82].copy_from_slice(&[
+            0,
+            0,
+            0,
+            RANGE_LIMITS_TAG,
+            offsets,
+            min_hz,
+            max_hz,
+            30,
+            160,
+            60,
+        ]);
+        edid[127] = 0u8.wrapping_sub(checksum(&edid));
+        edid
+    }
+
+    #[test]
+    fn parse_color_depth_and_cta_extension() {
+        let mut edid = test_edid(4, 0x08, 0, 48, 144);
+        // Digital input with 10 bits per component, one extension block
+        edid[20] = DIGITAL_INPUT | 0x30;
+        edid[126] = 1;
+        edid[127] = 0;
+        edid[127] = 0u8.wrapping_sub(checksum(&edid));
+        let mut extension = vec![0u8; BLOCK_LENGTH];
+        extension[..4].copy_from_slice(&[CTA_EXTENSION_TAG, 3, 12, CTA_YCBCR422]);
+        // HDR static metadata block with SMPTE ST 2084, then a YCbCr 4:2:0 capability map
+        extension[4..12].copy_from_slice(&[
+            (CTA_EXTENDED_TAG << 5) | 3,
+            CTA_HDR_STATIC_METADATA,
+            0x05,
+            0x01,
+            (CTA_EXTENDED_TAG << 5) | 2,
+            CTA_YCBCR420_CAPABILITY_MAP,
+            0xff,
+            0,
+        ]);
+        extension[127] = 0u8.wrapping_sub(checksum(&extension));
+        edid.extend(extension);
+
+        let edid = Edid::parse(&edid).unwrap();
+
+        assert_eq!(Some(10), edid.bits_per_component);
+        assert_eq!(
+            vec![
+                ColorFormat::Rgb,
+                ColorFormat::YCbCr444,
+                ColorFormat::YCbCr422,
+                ColorFormat::YCbCr420,
+            ],
+            edid.color_formats
+        );
+        assert_eq!(true, edid.hdr);
+        assert_eq!(
+            "up to 10bpc RGB/YCbCr 4:4:4/YCbCr 4:2:2/YCbCr 4:2:0 HDR",
+            edid.encoding_capabilities().to_string()
+        );
+    }
+
+    #[test]
+    fn parse_range_limits() {
+        let edid = Edid::parse(&test_edid(4, CONTINUOUS_FREQUENCY, 0, 48, 144)).unwrap();
+
+        assert_eq!(
+            Some(RangeLimits {
+                min_vertical_hz: 48,
+                max_vertical_hz: 144,
+                min_horizontal_khz: 30,
+                max_horizontal_khz: 160,
+                max_pixel_clock_mhz: 600,
+            }),
+            edid.range_limits
+        );
+    }
+
+    #[test]
+    fn parse_size() {
+        let mut edid = test_edid(4, 0, 0, 48, 144);
+        // 60x34 cm in the basic display parameters
+        edid[21] = 60;
+        edid[22] = 34;
+        edid[127] = 0;
+        edid[127] = 0u8.wrapping_sub(checksum(&edid));
+
+        assert_eq!(Some((600, 340)), Edid::parse(&edid).unwrap().size_mm);
+
+        // A detailed timing descriptor of 597x336 mm
+        edid[54..56].copy_from_slice(&[0x56, 0x5e]);
+        edid[66..69].copy_from_slice(&[0x55, 0x50, 0x21]);
+        edid[127] = 0;
+        edid[127] = 0u8.wrapping_sub(checksum(&edid));
+
+        assert_eq!(Some((597, 336)), Edid::parse(&edid).unwrap().size_mm);
+        assert_eq!(
+            None,
+            Edid::parse(&test_edid(4, 0, 0, 48, 144)).unwrap().size_mm
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let mut edid = test_edid(4, 0, 0, 48, 144);
+        edid[127] ^= 1;
+
+        assert_eq!(true, Edid::parse(&edid).is_err());
+        assert_eq!(true, Edid::parse(&edid[..64]).is_err());
+    }
+
+    #[test]
+    fn vrr_range_with_offset() {
+        let edid = Edid::parse(&test_edid(4, CONTINUOUS_FREQUENCY, 0x02, 48, 5)).unwrap();
+
+        assert_eq!(
+            Some(RefreshRate::Variable {
+                min: 48000,
+                max: 260000,
+            }),
+            edid.vrr_range()
+        );
+    }
+
+    #[test]
+    fn vrr_range_fixed_rate_displays() {
+        let narrow = Edid::parse(&test_edid(4, CONTINUOUS_FREQUENCY, 0, 56, 61)).unwrap();
+        let no_continuous = Edid::parse(&test_edid(4, 0, 0, 48, 144)).unwrap();
+        let edid_1_3 = Edid::parse(&test_edid(3, CONTINUOUS_FREQUENCY, 0, 48, 144)).unwrap();
+
+        assert_eq!(None, narrow.vrr_range());
+        assert_eq!(None, no_continuous.vrr_range());
+        assert_eq!(None, edid_1_3.vrr_range());
+    }
+}
@@ -0,0 +1,300 @@
+use core_graphics::display::{
+    kIO16BitFloatPixels, kIO30BitDirectPixels, kIO32BitFloatPixels, kIO64BitDirectPixels,
+    IO16BitDirectPixels, IO32BitDirectPixels, IOYUV422Pixels,
+};
+
+use std::fmt;
+use std::str::FromStr;
+
+use errors::*;
+
+/// How the color of a pixel is carried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorFormat {
+    Rgb,
+    YCbCr444,
+    YCbCr422,
+    YCbCr420,
+}
+
+impl ColorFormat {
+    /// The name used in mode strings and filters.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            ColorFormat::Rgb => "rgb",
+            ColorFormat::YCbCr444 => "ycbcr444",
+            ColorFormat::YCbCr422 => "ycbcr422",
+            ColorFormat::YCbCr420 => "ycbcr420",
+        }
+    }
+}
+
+impl fmt::Display for ColorFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                ColorFormat::Rgb => "RGB",
+                ColorFormat::YCbCr444 => "YCbCr 4:4:4",
+                ColorFormat::YCbCr422 => "YCbCr 4:2:2",
+                ColorFormat::YCbCr420 => "YCbCr 4:2:0",
+            }
+        )
+    }
+}
+
+/// Pixel encoding of a mode, beyond the bit depth that CoreGraphics reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PixelEncoding {
+    pub bits_per_component: u8,
+    pub format: ColorFormat,
+    /// Whether the display takes HDR signals in this encoding.
+    pub hdr: bool,
+}
+
+impl PixelEncoding {
+    /// The encoding of an IOKit pixel encoding string, as returned by
+    /// `CGDisplayMode::pixel_encoding`. Indexed encodings have none.
+    pub fn from_io_encoding(io_encoding: &str) -> Option<PixelEncoding> {
+        let (bits_per_component, format) = if io_encoding == IO32BitDirectPixels {
+            (8, ColorFormat::Rgb)
+        } else if io_encoding == kIO30BitDirectPixels {
+            (10, ColorFormat::Rgb)
+        } else if io_encoding == kIO64BitDirectPixels || io_encoding == kIO16BitFloatPixels {
+            (16, ColorFormat::Rgb)
+        } else if io_encoding == kIO32BitFloatPixels {
+            (32, ColorFormat::Rgb)
+        } else if io_encoding == IO16BitDirectPixels {
+            (5, ColorFormat::Rgb)
+        } else if io_encoding == IOYUV422Pixels {
+            (8, ColorFormat::YCbCr422)
+        } else {
+            return None;
+        };
+        Some(PixelEncoding {
+            bits_per_component,
+            format,
+            hdr: false,
+        })
+    }
+}
+
+/// Encodings are written like `10bpc RGB HDR`.
+impl fmt::Display for PixelEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}bpc {}", self.bits_per_component, self.format)?;
+        if self.hdr {
+            write!(f, " HDR")?;
+        }
+        Ok(())
+    }
+}
+
+/// Encodings a display takes on its link, from the color depth, color formats and HDR support in
+/// its EDID. The encoding CoreGraphics reports for a mode is the one of the frame buffer, which
+/// does not tell what else the display could take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodingCapabilities {
+    pub max_bits_per_component: u8,
+    pub formats: Vec<ColorFormat>,
+    pub hdr: bool,
+}
+
+impl EncodingCapabilities {
+    /// Whether the display can take an encoding with the wanted parts.
+    pub fn allows(&self, filter: &EncodingFilter) -> bool {
+        let hdr = filter.hdr == Some(true);
+        // HDR signals need at least 10 bits per component
+        let bits = match filter.bits_per_component {
+            Some(bits) if hdr => bits.max(10),
+            Some(bits) => bits,
+            None if hdr => 10,
+            None => 8,
+        };
+        bits <= self.max_bits_per_component
+            && (!hdr || self.hdr)
+            && filter
+                .format
+                .map_or(true, |format| self.formats.contains(&format))
+    }
+}
+
+/// Capabilities are written like `up to 10bpc RGB/YCbCr 4:4:4 HDR`.
+impl fmt::Display for EncodingCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formats: Vec<String> = self
+            .formats
+            .iter()
+            .map(|format| format.to_string())
+            .collect();
+        write!(
+            f,
+            "up to {}bpc {}",
+            self.max_bits_per_component,
+            formats.join("/")
+        )?;
+        if self.hdr {
+            write!(f, " HDR")?;
+        }
+        Ok(())
+    }
+}
+
+/// Wanted parts of an encoding, as given after a mode (`3840x2160@60:10bpc:hdr`) or to
+/// `list --encoding`.
+#[derive(Debug, Default, PartialEq)]
+pub struct EncodingFilter {
+    pub bits_per_component: Option<u8>,
+    pub format: Option<ColorFormat>,
+    pub hdr: Option<bool>,
+}
+
+impl EncodingFilter {
+    pub fn matches(&self, encoding: Option<&PixelEncoding>) -> bool {
+        match encoding {
+            Some(encoding) => {
+                self.bits_per_component
+                    .map_or(true, |bits| bits == encoding.bits_per_component)
+                    && self.format.map_or(true, |format| format == encoding.format)
+                    && self.hdr.map_or(true, |hdr| hdr == encoding.hdr)
+            }
+            None => *self == EncodingFilter::default(),
+        }
+    }
+}
+
+/// Parts are separated by colons: a depth like `10bpc`, a format like `ycbcr422`, `hdr` or `sdr`.
+impl FromStr for EncodingFilter {
+    type Err = Error;
+
+    fn from_str(filter: &str) -> Result<EncodingFilter> {
+        let mut encoding_filter = EncodingFilter::default();
+        for part in filter.split(':').map(|part| part.trim().to_lowercase()) {
+            let formats = [
+                ColorFormat::Rgb,
+                ColorFormat::YCbCr444,
+                ColorFormat::YCbCr422,
+                ColorFormat::YCbCr420,
+            ];
+            if part == "hdr" || part == "sdr" {
+                encoding_filter.hdr = Some(part == "hdr");
+            } else if let Some(&format) = formats.iter().find(|format| format.key() == part) {
+                encoding_filter.format = Some(format);
+            } else if part.ends_with("bpc") {
+                encoding_filter.bits_per_component = Some(
+                    part.trim_end_matches("bpc")
+                        .parse()
+                        .chain_err(|| format!("Not a valid bit depth: {}", part))?,
+                );
+            } else {
+                return Err(format!("Not a valid pixel encoding: {}", part).into());
+            }
+        }
+        Ok(encoding_filter)
+    }
+}
+
+/// Split the encoding off a mode string: `3840x2160@60:10bpc` gives `3840x2160@60` and `10bpc`.
+pub fn split_mode(mode: &str) -> (&str, Option<&str>) {
+    match mode.find(':') {
+        Some(position) => (&mode[..position], Some(&mode[position + 1..])),
+        None => (mode, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_io_encoding() {
+        assert_eq!(
+            Some(PixelEncoding {
+                bits_per_component: 10,
+                format: ColorFormat::Rgb,
+                hdr: false,
+            }),
+            PixelEncoding::from_io_encoding("--RRRRRRRRRRGGGGGGGGGGBBBBBBBBBB")
+        );
+        assert_eq!(None, PixelEncoding::from_io_encoding("PPPPPPPP"));
+    }
+
+    #[test]
+    fn display_encoding() {
+        let encoding = PixelEncoding {
+            bits_per_component: 10,
+            format: ColorFormat::YCbCr422,
+            hdr: true,
+        };
+
+        assert_eq!("10bpc YCbCr 4:2:2 HDR", encoding.to_string());
+    }
+
+    #[test]
+    fn parse_filter() {
+        assert_eq!(
+            EncodingFilter {
+                bits_per_component: Some(10),
+                format: Some(ColorFormat::YCbCr420),
+                hdr: Some(true),
+            },
+            "10bpc:YCbCr420:hdr".parse::<EncodingFilter>().unwrap()
+        );
+        assert_eq!(true, "deep".parse::<EncodingFilter>().is_err());
+        assert_eq!(true, "tenbpc".parse::<EncodingFilter>().is_err());
+    }
+
+    #[test]
+    fn filter_matches() {
+        let encoding = PixelEncoding {
+            bits_per_component: 8,
+            format: ColorFormat::Rgb,
+            hdr: false,
+        };
+        let filter: EncodingFilter = "8bpc:sdr".parse().unwrap();
+
+        assert_eq!(true, filter.matches(Some(&encoding)));
+        assert_eq!(false, filter.matches(None));
+        assert_eq!(true, EncodingFilter::default().matches(None));
+        assert_eq!(
+            false,
+            "10bpc"
+                .parse::<EncodingFilter>()
+                .unwrap()
+                .matches(Some(&encoding))
+        );
+    }
+
+    #[test]
+    fn capabilities_allow() {
+        let capabilities = EncodingCapabilities {
+            max_bits_per_component: 12,
+            formats: vec![ColorFormat::Rgb, ColorFormat::YCbCr422],
+            hdr: true,
+        };
+        let allows = |filter: &str| capabilities.allows(&filter.parse().unwrap());
+
+        assert_eq!(true, allows("12bpc"));
+        assert_eq!(true, allows("ycbcr422:hdr"));
+        assert_eq!(false, allows("16bpc"));
+        assert_eq!(false, allows("ycbcr420"));
+        assert_eq!(
+            false,
+            EncodingCapabilities {
+                max_bits_per_component: 8,
+                ..capabilities.clone()
+            }.allows(&"hdr".parse().unwrap())
+        );
+        assert_eq!("up to 12bpc RGB/YCbCr 4:2:2 HDR", capabilities.to_string());
+    }
+
+    #[test]
+    fn split_mode_encoding() {
+        assert_eq!(
+            ("3840x2160@60", Some("10bpc:hdr")),
+            split_mode("3840x2160@60:10bpc:hdr")
+        );
+        assert_eq!(("3840x2160@60", None), split_mode("3840x2160@60"));
+    }
+}
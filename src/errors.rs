@@ -3,14 +3,113 @@ use std::error;
 use std::fmt;
 use std::result;
 
-#[derive(Debug)]
-pub struct CGError {
-    error: base::CGError,
+use mode::DisplayIndex;
+
+/// A decoded CoreGraphics error code (see CGError.h).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CGError {
+    Failure,
+    IllegalArgument,
+    InvalidConnection,
+    InvalidContext,
+    CannotComplete,
+    NotImplemented,
+    RangeCheck,
+    TypeCheck,
+    InvalidOperation,
+    NoneAvailable,
+    Unknown(i32),
+}
+
+impl CGError {
+    pub fn from_code(code: i32) -> CGError {
+        match code {
+            1000 => CGError::Failure,
+            1001 => CGError::IllegalArgument,
+            1002 => CGError::InvalidConnection,
+            1003 => CGError::InvalidContext,
+            1004 => CGError::CannotComplete,
+            1006 => CGError::NotImplemented,
+            1007 => CGError::RangeCheck,
+            1008 => CGError::TypeCheck,
+            1010 => CGError::InvalidOperation,
+            1011 => CGError::NoneAvailable,
+            code => CGError::Unknown(code),
+        }
+    }
+
+    /// The numeric CGError code.
+    pub fn code(&self) -> i32 {
+        match *self {
+            CGError::Failure => 1000,
+            CGError::IllegalArgument => 1001,
+            CGError::InvalidConnection => 1002,
+            CGError::InvalidContext => 1003,
+            CGError::CannotComplete => 1004,
+            CGError::NotImplemented => 1006,
+            CGError::RangeCheck => 1007,
+            CGError::TypeCheck => 1008,
+            CGError::InvalidOperation => 1010,
+            CGError::NoneAvailable => 1011,
+            CGError::Unknown(code) => code,
+        }
+    }
+
+    /// The CoreGraphics constant name for this error.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CGError::Failure => "kCGErrorFailure",
+            CGError::IllegalArgument => "kCGErrorIllegalArgument",
+            CGError::InvalidConnection => "kCGErrorInvalidConnection",
+            CGError::InvalidContext => "kCGErrorInvalidContext",
+            CGError::CannotComplete => "kCGErrorCannotComplete",
+            CGError::NotImplemented => "kCGErrorNotImplemented",
+            CGError::RangeCheck => "kCGErrorRangeCheck",
+            CGError::TypeCheck => "kCGErrorTypeCheck",
+            CGError::InvalidOperation => "kCGErrorInvalidOperation",
+            CGError::NoneAvailable => "kCGErrorNoneAvailable",
+            CGError::Unknown(_) => "unknown CGError",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match *self {
+            CGError::Failure => "the display server reported a general failure",
+            CGError::IllegalArgument => "one or more arguments were not valid",
+            CGError::InvalidConnection => "the connection to the window server is not valid",
+            CGError::InvalidContext => "the graphics context is not valid",
+            CGError::CannotComplete => "the requested operation could not be completed",
+            CGError::NotImplemented => "the requested operation is not supported",
+            CGError::RangeCheck => "a parameter is outside its accepted range",
+            CGError::TypeCheck => "a data type or token was encountered where it was not expected",
+            CGError::InvalidOperation => "the requested operation is not valid",
+            CGError::NoneAvailable => "the requested resource is not available",
+            CGError::Unknown(_) => "an unknown error occurred",
+        }
+    }
+
+    /// What the user can try to get around this error, if anything.
+    pub fn hint(&self) -> Option<&'static str> {
+        match *self {
+            CGError::IllegalArgument | CGError::RangeCheck => {
+                Some("check that the mode is listed for this display with `list`")
+            }
+            CGError::InvalidConnection => {
+                Some("run the tool from a logged in graphical session, not over ssh")
+            }
+            CGError::CannotComplete => {
+                Some("another application may be configuring the displays, try again")
+            }
+            CGError::NotImplemented => Some("this display does not support the requested change"),
+            CGError::NoneAvailable => Some("the display may have been disconnected, try again"),
+            _ => None,
+        }
+    }
 }
 
 impl error::Error for CGError {
     fn description(&self) -> &str {
-        "a CG error"
+        self.message()
     }
 
     fn cause(&self) -> Option<&error::Error> {
@@ -20,13 +119,17 @@ impl error::Error for CGError {
 
 impl fmt::Display for CGError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "CGError: {}", self)
+        write!(f, "{} ({} {})", self.message(), self.name(), self.code())?;
+        if let Some(hint) = self.hint() {
+            write!(f, ", {}", hint)?;
+        }
+        Ok(())
     }
 }
 
 impl From<base::CGError> for CGError {
     fn from(e: base::CGError) -> Self {
-        CGError { error: e }
+        CGError::from_code(e)
     }
 }
 
@@ -36,8 +139,128 @@ error_chain!{
         CgError(CGError);
         IoError(::std::io::Error);
     }
+
+    errors {
+        NoSuchDisplay(display: DisplayIndex) {
+            description("no such display")
+            display("No such display: {}", display)
+        }
+        NoDisplayMatching(display: String) {
+            description("no such display")
+            display("No display matching: {}", display)
+        }
+        NoMatchingMode(mode: String, display: DisplayIndex) {
+            description("no matching mode")
+            display("No mode matching {} on display {}", mode, display)
+        }
+        AlreadyCurrent(mode: String, display: DisplayIndex) {
+            description("wanted mode is already current")
+            display("Mode {} is already current on display {}", mode, display)
+        }
+        ConfigurationRejected(error: CGError) {
+            description("display configuration was rejected")
+            display("Display configuration was rejected: {}", error)
+        }
+        BackendError(error: CGError) {
+            description("display backend error")
+            display("Display backend error: {}", error)
+        }
+        VerificationFailed(wanted: String, observed: String, display: DisplayIndex) {
+            description("applied mode could not be verified")
+            display("Display {} is at {} instead of {}", display, observed, wanted)
+        }
+        HookVetoed(command: String, status: String) {
+            description("pre-hook vetoed the change")
+            display("Pre-hook `{}` vetoed the change ({})", command, status)
+        }
+        UnsafeMode(mode: String, display: DisplayIndex) {
+            description("mode is not marked safe for the display")
+            display("Mode {} is not marked safe for display {}, use --force to set it anyway", mode, display)
+        }
+        CommandFailed(code: i32) {
+            description("command run by exec failed")
+            display("Command exited with status {}", code)
+        }
+    }
+}
+
+/// Exit codes returned by the tool. Errors that are not listed here exit with 1, except when
+/// the command run by `exec` fails: the tool then exits with the exit code of the command.
+pub const EXIT_NO_SUCH_DISPLAY: i32 = 2;
+pub const EXIT_NO_MATCHING_MODE: i32 = 3;
+pub const EXIT_ALREADY_CURRENT: i32 = 4;
+pub const EXIT_CONFIGURATION_REJECTED: i32 = 5;
+pub const EXIT_BACKEND_ERROR: i32 = 6;
+pub const EXIT_VERIFICATION_FAILED: i32 = 7;
+pub const EXIT_HOOK_VETOED: i32 = 8;
+pub const EXIT_UNSAFE_MODE: i32 = 9;
+
+impl Error {
+    /// The process exit code to use when the tool stops because of this error.
+    pub fn exit_code(&self) -> i32 {
+        match *self.kind() {
+            ErrorKind::NoSuchDisplay(_) | ErrorKind::NoDisplayMatching(_) => EXIT_NO_SUCH_DISPLAY,
+            ErrorKind::NoMatchingMode(_, _) => EXIT_NO_MATCHING_MODE,
+            ErrorKind::AlreadyCurrent(_, _) => EXIT_ALREADY_CURRENT,
+            ErrorKind::ConfigurationRejected(_) => EXIT_CONFIGURATION_REJECTED,
+            ErrorKind::BackendError(_) => EXIT_BACKEND_ERROR,
+            ErrorKind::VerificationFailed(_, _, _) => EXIT_VERIFICATION_FAILED,
+            ErrorKind::HookVetoed(_, _) => EXIT_HOOK_VETOED,
+            ErrorKind::UnsafeMode(_, _) => EXIT_UNSAFE_MODE,
+            ErrorKind::CommandFailed(code) => code,
+            _ => 1,
+        }
+    }
 }
 
 pub fn convert_result<T>(result: result::Result<T, base::CGError>) -> result::Result<T, CGError> {
-    result.map_err(|e: base::CGError| CGError { error: e })
+    result.map_err(CGError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cg_error_from_code() {
+        assert_eq!(CGError::IllegalArgument, CGError::from_code(1001));
+        assert_eq!(CGError::NoneAvailable, CGError::from_code(1011));
+        assert_eq!(CGError::Unknown(1005), CGError::from_code(1005));
+    }
+
+    #[test]
+    fn cg_error_code_round_trip() {
+        for code in 999..1013 {
+            assert_eq!(code, CGError::from_code(code).code());
+        }
+    }
+
+    #[test]
+    fn cg_error_display_with_hint() {
+        assert_eq!(
+            "the connection to the window server is not valid (kCGErrorInvalidConnection 1002), \
+             run the tool from a logged in graphical session, not over ssh",
+            format!("{}", CGError::from_code(1002))
+        );
+    }
+
+    #[test]
+    fn cg_error_display_unknown() {
+        assert_eq!(
+            "an unknown error occurred (unknown CGError 42)",
+            format!("{}", CGError::from_code(42))
+        );
+    }
+
+    #[test]
+    fn no_display_matching_exit_code() {
+        let error: Error = ErrorKind::NoDisplayMatching("10ac:a0c4".to_string()).into();
+        assert_eq!(EXIT_NO_SUCH_DISPLAY, error.exit_code());
+    }
+
+    #[test]
+    fn cg_error_exit_code() {
+        let error: Error = ErrorKind::ConfigurationRejected(CGError::CannotComplete).into();
+        assert_eq!(EXIT_CONFIGURATION_REJECTED, error.exit_code());
+    }
 }
@@ -0,0 +1,498 @@
+use core_foundation::runloop::{kCFRunLoopDefaultMode, CFRunLoopRunInMode};
+use core_graphics::base;
+use core_graphics::display::{CGDirectDisplayID, CGDisplay};
+
+use libc::c_void;
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use errors::*;
+use mode::*;
+
+// Flags passed to display reconfiguration callbacks (see CGDisplayConfiguration.h).
+const K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG: u32 = 1 << 0;
+const K_CG_DISPLAY_SET_MAIN_FLAG: u32 = 1 << 2;
+const K_CG_DISPLAY_SET_MODE_FLAG: u32 = 1 << 3;
+const K_CG_DISPLAY_ADD_FLAG: u32 = 1 << 4;
+const K_CG_DISPLAY_REMOVE_FLAG: u32 = 1 << 5;
+
+type CGDisplayReconfigurationCallBack =
+    extern "C" fn(display: CGDirectDisplayID, flags: u32, user_info: *mut c_void);
+
+extern "C" {
+    fn CGDisplayRegisterReconfigurationCallback(
+        callback: CGDisplayReconfigurationCallBack,
+        user_info: *mut c_void,
+    ) -> base::CGError;
+    fn CGDisplayRemoveReconfigurationCallback(
+        callback: CGDisplayReconfigurationCallBack,
+        user_info: *mut c_void,
+    ) -> base::CGError;
+}
+
+/// Something that happened to a display.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayEvent {
+    Connected(CGDirectDisplayID),
+    Disconnected(CGDirectDisplayID),
+    /// The display is now in the specified mode, in the `for_select` format.
+    ModeChanged(CGDirectDisplayID, String),
+    BecameMain(CGDirectDisplayID),
+}
+
+impl DisplayEvent {
+    /// The display the event is about.
+    pub fn display(&self) -> CGDirectDisplayID {
+        match *self {
+            DisplayEvent::Connected(id)
+            | DisplayEvent::Disconnected(id)
+            | DisplayEvent::ModeChanged(id, _)
+            | DisplayEvent::BecameMain(id) => id,
+        }
+    }
+
+    pub fn print_event(&self, json: bool, output: &mut io::Write) -> Result<()> {
+        match (json, self) {
+            (false, &DisplayEvent::Connected(id)) => write!(output, "Display {} connected", id),
+            (false, &DisplayEvent::Disconnected(id)) => {
+                write!(output, "Display {} disconnected", id)
+            }
+            (false, &DisplayEvent::ModeChanged(id, ref mode)) => {
+                write!(output, "Display {} mode changed to {}", id, mode)
+            }
+            (false, &DisplayEvent::BecameMain(id)) => write!(output, "Display {} became main", id),
+            (true, &DisplayEvent::Connected(id)) => {
+                write!(output, r#"{{"event":"connected","display":{}}}"#, id)
+            }
+            (true, &DisplayEvent::Disconnected(id)) => {
+                write!(output, r#"{{"event":"disconnected","display":{}}}"#, id)
+            }
+            (true, &DisplayEvent::ModeChanged(id, ref mode)) => write!(
+                output,
+                r#"{{"event":"mode_changed","display":{},"mode":"{}"}}"#,
+                id, mode
+            ),
+            (true, &DisplayEvent::BecameMain(id)) => {
+                write!(output, r#"{{"event":"became_main","display":{}}}"#, id)
+            }
+        }.chain_err(|| "Could not print event")?;
+        Ok(())
+    }
+}
+
+/// A source of display events.
+pub trait DisplayEventSource {
+    /// Block until an event is available, for at most `timeout` when specified. Returns `None`
+    /// when the timeout expired or when the source is exhausted.
+    fn next_event(&mut self, timeout: Option<Duration>) -> Result<Option<DisplayEvent>>;
+
+    /// Whether the source will never produce any more events.
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+}
+
+/// Translate the flags of a reconfiguration callback into events. `current_mode` is only called
+/// when the mode of the display changed.
+pub fn events_from_flags<F>(
+    display_id: CGDirectDisplayID,
+    flags: u32,
+    current_mode: F,
+) -> Vec<DisplayEvent>
+where
+    F: Fn(CGDirectDisplayID) -> Option<String>,
+{
+    let mut events = Vec::new();
+    // The callback is called once before and once after the change, only the latter matters.
+    if flags & K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG != 0 {
+        return events;
+    }
+    if flags & K_CG_DISPLAY_ADD_FLAG != 0 {
+        events.push(DisplayEvent::Connected(display_id));
+    }
+    if flags & K_CG_DISPLAY_REMOVE_FLAG != 0 {
+        events.push(DisplayEvent::Disconnected(display_id));
+    }
+    if flags & K_CG_DISPLAY_SET_MODE_FLAG != 0 {
+        if let Some(mode) = current_mode(display_id) {
+            events.push(DisplayEvent::ModeChanged(display_id, mode));
+        }
+    }
+    if flags & K_CG_DISPLAY_SET_MAIN_FLAG != 0 {
+        events.push(DisplayEvent::BecameMain(display_id));
+    }
+    events
+}
+
+fn display_current_mode(display_id: CGDirectDisplayID) -> Option<String> {
+    CGDisplay::new(display_id)
+        .display_mode()
+        .map(|cgmode| Mode::from(0, cgmode).for_select())
+}
+
+extern "C" fn reconfiguration_callback(
+    display_id: CGDirectDisplayID,
+    flags: u32,
+    user_info: *mut c_void,
+) {
+    let sender = unsafe { &*(user_info as *const Sender<DisplayEvent>) };
+    for event in events_from_flags(display_id, flags, display_current_mode) {
+        let _ = sender.send(event);
+    }
+}
+
+/// Events reported by CoreGraphics through a display reconfiguration callback.
+pub struct ReconfigurationEventSource {
+    sender: Box<Sender<DisplayEvent>>,
+    receiver: Receiver<DisplayEvent>,
+}
+
+impl ReconfigurationEventSource {
+    pub fn new() -> Result<ReconfigurationEventSource> {
+        let (sender, receiver) = channel();
+        let sender = Box::new(sender);
+        let error = unsafe {
+            CGDisplayRegisterReconfigurationCallback(
+                reconfiguration_callback,
+                &*sender as *const Sender<DisplayEvent> as *mut c_void,
+            )
+        };
+        if error != 0 {
+            return Err(ErrorKind::BackendError(error.into()).into());
+        }
+        Ok(ReconfigurationEventSource { sender, receiver })
+    }
+}
+
+impl DisplayEventSource for ReconfigurationEventSource {
+    fn next_event(&mut self, timeout: Option<Duration>) -> Result<Option<DisplayEvent>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => return Ok(Some(event)),
+                Err(TryRecvError::Disconnected) => return Ok(None),
+                Err(TryRecvError::Empty) => {
+                    let mut slice = Duration::from_secs(1);
+                    if let Some(deadline) = deadline {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return Ok(None);
+                        }
+                        slice = slice.min(deadline - now);
+                    }
+                    let seconds = slice.as_secs() as f64 + f64::from(slice.subsec_nanos()) / 1e9;
+                    // Callbacks are delivered while the run loop runs.
+                    unsafe {
+                        CFRunLoopRunInMode(kCFRunLoopDefaultMode, seconds, 1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ReconfigurationEventSource {
+    fn drop(&mut self) {
+        unsafe {
+            CGDisplayRemoveReconfigurationCallback(
+                reconfiguration_callback,
+                &*self.sender as *const Sender<DisplayEvent> as *mut c_void,
+            );
+        }
+    }
+}
+
+/// What a polling event source knows about a display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayState {
+    pub display_id: CGDirectDisplayID,
+    pub mode: String,
+    pub main: bool,
+}
+
+/// The state of all active displays, as reported by CoreGraphics.
+pub fn active_display_states() -> Result<Vec<DisplayState>> {
+    let displays = convert_result(CGDisplay::active_displays())
+        .chain_err(|| "Unable to list active displays")?;
+    Ok(displays
+        .into_iter()
+        .map(|display_id| DisplayState {
+            display_id,
+            mode: display_current_mode(display_id).unwrap_or_default(),
+            main: CGDisplay::new(display_id).is_main(),
+        }).collect())
+}
+
+/// Events found by comparing two successive snapshots of the displays.
+pub fn events_from_states(
+    previous: &[DisplayState],
+    current: &[DisplayState],
+) -> Vec<DisplayEvent> {
+    let mut events = Vec::new();
+    for state in previous {
+        if !current.iter().any(|s| s.display_id == state.display_id) {
+            events.push(DisplayEvent::Disconnected(state.display_id));
+        }
+    }
+    for state in current {
+        match previous
+            .iter()
+            .filter(|s| s.display_id == state.display_id)
+            .next()
+        {
+            None => {
+                events.push(DisplayEvent::Connected(state.display_id));
+                if state.main {
+                    events.push(DisplayEvent::BecameMain(state.display_id));
+                }
+            }
+            Some(previous) => {
+                if previous.mode != state.mode {
+                    events.push(DisplayEvent::ModeChanged(
+                        state.display_id,
+                        state.mode.clone(),
+                    ));
+                }
+                if !previous.main && state.main {
+                    events.push(DisplayEvent::BecameMain(state.display_id));
+                }
+            }
+        }
+    }
+    events
+}
+
+/// Events found by taking a snapshot of the displays at a regular interval, for when no callback
+/// is available.
+pub struct PollingEventSource<F>
+where
+    F: FnMut() -> Result<Vec<DisplayState>>,
+{
+    snapshot: F,
+    interval: Duration,
+    states: Option<Vec<DisplayState>>,
+    pending: VecDeque<DisplayEvent>,
+}
+
+impl<F> PollingEventSource<F>
+where
+    F: FnMut() -> Result<Vec<DisplayState>>,
+{
+    pub fn new(snapshot: F, interval: Duration) -> PollingEventSource<F> {
+        PollingEventSource {
+            snapshot,
+            interval,
+            states: None,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<F> DisplayEventSource for PollingEventSource<F>
+where
+    F: FnMut() -> Result<Vec<DisplayState>>,
+{
+    fn next_event(&mut self, timeout: Option<Duration>) -> Result<Option<DisplayEvent>> {
+        let start = Instant::now();
+        while self.pending.is_empty() {
+            if timeout.map_or(false, |timeout| start.elapsed() >= timeout) {
+                return Ok(None);
+            }
+            let states = (self.snapshot)()?;
+            match self.states {
+                // The first snapshot is the starting point, not a change.
+                None => (),
+                Some(ref previous) => {
+                    self.pending.extend(events_from_states(previous, &states));
+                    if self.pending.is_empty() {
+                        thread::sleep(self.interval);
+                    }
+                }
+            }
+            self.states = Some(states);
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+/// Events given in advance, for testing.
+#[cfg(test)]
+pub struct MemoryEventSource {
+    events: VecDeque<DisplayEvent>,
+}
+
+#[cfg(test)]
+impl MemoryEventSource {
+    pub fn new(events: Vec<DisplayEvent>) -> MemoryEventSource {
+        MemoryEventSource {
+            events: events.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl DisplayEventSource for MemoryEventSource {
+    fn next_event(&mut self, _timeout: Option<Duration>) -> Result<Option<DisplayEvent>> {
+        Ok(self.events.pop_front())
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// Print every event of the source, or only the ones about the specified display, one per line,
+/// until it runs out of events.
+pub fn watch(
+    source: &mut DisplayEventSource,
+    json: bool,
+    display: Option<CGDirectDisplayID>,
+    output: &mut io::Write,
+) -> Result<()> {
+    while let Some(event) = source.next_event(None)? {
+        if display.map_or(false, |display| display != event.display()) {
+            continue;
+        }
+        event.print_event(json, output)?;
+        writeln!(output, "")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state(display_id: CGDirectDisplayID, mode: &str, main: bool) -> DisplayState {
+        DisplayState {
+            display_id,
+            mode: mode.to_string(),
+            main,
+        }
+    }
+
+    #[test]
+    fn events_from_flags_begin_configuration() {
+        let events = events_from_flags(
+            1,
+            K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG | K_CG_DISPLAY_SET_MODE_FLAG,
+            |_| Some("800x600x32@60".to_string()),
+        );
+        assert_eq!(Vec::<DisplayEvent>::new(), events);
+    }
+
+    #[test]
+    fn events_from_flags_add_and_mode() {
+        let events = events_from_flags(
+            1,
+            K_CG_DISPLAY_ADD_FLAG | K_CG_DISPLAY_SET_MODE_FLAG,
+            |_| Some("800x600x32@60".to_string()),
+        );
+        assert_eq!(
+            vec![
+                DisplayEvent::Connected(1),
+                DisplayEvent::ModeChanged(1, "800x600x32@60".to_string()),
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn events_from_states_changes() {
+        let previous = vec![
+            test_state(1, "1440x900x32@0", true),
+            test_state(2, "1920x1200x32@60", false),
+        ];
+        let current = vec![
+            test_state(1, "1280x800x32@0", false),
+            test_state(3, "1024x768x32@60", true),
+        ];
+        assert_eq!(
+            vec![
+                DisplayEvent::Disconnected(2),
+                DisplayEvent::ModeChanged(1, "1280x800x32@0".to_string()),
+                DisplayEvent::Connected(3),
+                DisplayEvent::BecameMain(3),
+            ],
+            events_from_states(&previous, &current)
+        );
+    }
+
+    #[test]
+    fn polling_event_source() {
+        let mut snapshots = vec![
+            vec![test_state(1, "1280x800x32@0", true)],
+            vec![test_state(1, "1440x900x32@0", true)],
+            vec![test_state(1, "1440x900x32@0", true)],
+        ];
+        let mut source =
+            PollingEventSource::new(|| Ok(snapshots.pop().unwrap()), Duration::from_millis(0));
+
+        let event = source.next_event(None).unwrap();
+
+        assert_eq!(
+            Some(DisplayEvent::ModeChanged(1, "1280x800x32@0".to_string())),
+            event
+        );
+    }
+
+    #[test]
+    fn watch_text() {
+        let mut source = MemoryEventSource::new(vec![
+            DisplayEvent::Connected(2),
+            DisplayEvent::ModeChanged(2, "1920x1200x32@60".to_string()),
+            DisplayEvent::BecameMain(2),
+            DisplayEvent::Disconnected(2),
+        ]);
+        let mut vec = Vec::<u8>::new();
+
+        watch(&mut source, false, None, &mut vec).expect("Error while testing watch");
+
+        assert_eq!(
+            "Display 2 connected\n\
+             Display 2 mode changed to 1920x1200x32@60\n\
+             Display 2 became main\n\
+             Display 2 disconnected\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn watch_one_display() {
+        let mut source = MemoryEventSource::new(vec![
+            DisplayEvent::Connected(2),
+            DisplayEvent::ModeChanged(3, "1920x1200x32@60".to_string()),
+            DisplayEvent::Disconnected(2),
+        ]);
+        let mut vec = Vec::<u8>::new();
+
+        watch(&mut source, false, Some(3), &mut vec).expect("Error while testing watch");
+
+        assert_eq!(
+            "Display 3 mode changed to 1920x1200x32@60\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn watch_json() {
+        let mut source = MemoryEventSource::new(vec![
+            DisplayEvent::Connected(2),
+            DisplayEvent::ModeChanged(2, "1920x1200x32@60".to_string()),
+        ]);
+        let mut vec = Vec::<u8>::new();
+
+        watch(&mut source, true, None, &mut vec).expect("Error while testing watch");
+
+        assert_eq!(
+            "{\"event\":\"connected\",\"display\":2}\n\
+             {\"event\":\"mode_changed\",\"display\":2,\"mode\":\"1920x1200x32@60\"}\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use libc;
+
+use std::os::unix::process::ExitStatusExt;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use errors::*;
+use record::Record;
+
+/// The mode to restore once `exec` is done, persisted while the command runs so that it can
+/// still be restored when the tool died before restoring it.
+#[derive(Debug, PartialEq)]
+pub struct PendingRestore {
+    /// Process that will restore the mode.
+    pub pid: u32,
+    /// Identity of the display, see `ScreenResolution::display_identity`.
+    pub display: String,
+    pub mode: String,
+}
+
+impl Record for PendingRestore {
+    const FILE_NAME: &'static str = "pending-restore";
+    const NAME: &'static str = "pending restore";
+    const FIELDS: usize = 2;
+
+    fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    fn fields(&self) -> Vec<&str> {
+        vec![&self.display, &self.mode]
+    }
+
+    fn from_fields(pid: u32, fields: &[&str]) -> PendingRestore {
+        PendingRestore {
+            pid,
+            display: fields[0].to_string(),
+            mode: fields[1].to_string(),
+        }
+    }
+}
+
+static SIGNAL: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    SIGNAL.store(signal as usize, Ordering::SeqCst);
+}
+
+/// Catches SIGINT and SIGTERM until dropped, then gives them their default action back.
+struct SignalGuard;
+
+impl SignalGuard {
+    fn catch() -> SignalGuard {
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+            libc::signal(
+                libc::SIGTERM,
+                on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+        SignalGuard
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGTERM, libc::SIG_DFL);
+        }
+    }
+}
+
+/// Run the command until it exits and return its exit code, or 128 plus the signal number when
+/// it was killed by a signal. SIGINT and SIGTERM do not stop the tool while the command runs:
+/// SIGINT already reaches the command through the terminal and SIGTERM is passed on to it.
+pub fn run_command(command: &[&str]) -> Result<i32> {
+    let (program, args) = command.split_first().ok_or("No command to run")?;
+    let _guard = SignalGuard::catch();
+    let mut child = Command::new(program)
+        .args(args)
+        .spawn()
+        .chain_err(|| format!("Could not run command: {}", program))?;
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .chain_err(|| "Could not wait for command")?
+        {
+            break status;
+        }
+        if SIGNAL.swap(0, Ordering::SeqCst) == libc::SIGTERM as usize {
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    Ok(status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn pending_restore_round_trip() {
+        let path = env::temp_dir().join(format!(
+            "screenresolution-rs-pending-restore-{}",
+            ::std::process::id()
+        ));
+        let pending = PendingRestore {
+            pid: 42,
+            display: "0610:a050:00000000".to_string(),
+            mode: "1440x900x32@0".to_string(),
+        };
+
+        pending.save(&path).unwrap();
+        assert_eq!(Some(pending), PendingRestore::load(&path).unwrap());
+
+        PendingRestore::clear(&path).unwrap();
+        assert_eq!(None, PendingRestore::load(&path).unwrap());
+    }
+
+    #[test]
+    fn pending_restore_stale() {
+        let pending = PendingRestore {
+            pid: ::std::process::id(),
+            display: "0610:a050:00000000".to_string(),
+            mode: "1440x900x32@0".to_string(),
+        };
+        assert_eq!(false, pending.is_stale());
+
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        let pending = PendingRestore {
+            pid: child.id(),
+            ..pending
+        };
+        assert_eq!(true, pending.is_stale());
+    }
+
+    #[test]
+    fn run_command_exit_code() {
+        assert_eq!(0, run_command(&["true"]).unwrap());
+        assert_eq!(3, run_command(&["sh", "-c", "exit 3"]).unwrap());
+        assert_eq!(128 + 9, run_command(&["sh", "-c", "kill -9 $$"]).unwrap());
+        assert_eq!(
+            true,
+            run_command(&["screenresolution-rs-no-such-command"]).is_err()
+        );
+
+        // Checked in the only test catching signals, as tests run in parallel
+        let handler = unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+        assert_eq!(libc::SIG_DFL, handler);
+    }
+}
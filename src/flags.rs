@@ -0,0 +1,148 @@
+use core_graphics::display::{
+    kDisplayModeAcceleratorBackedFlag, kDisplayModeAlwaysShowFlag, kDisplayModeBuiltInFlag,
+    kDisplayModeDefaultFlag, kDisplayModeInterlacedFlag, kDisplayModeNativeFlag,
+    kDisplayModeNeverShowFlag, kDisplayModeNotGraphicsQualityFlag, kDisplayModeNotPresetFlag,
+    kDisplayModeNotResizeFlag, kDisplayModeRequiresPanFlag, kDisplayModeSafeFlag,
+    kDisplayModeSimulscanFlag, kDisplayModeStretchedFlag, kDisplayModeTelevisionFlag,
+    kDisplayModeValidFlag, kDisplayModeValidForAirPlayFlag, kDisplayModeValidForHiResFlag,
+    kDisplayModeValidForMirroringFlag, kDisplayModeValidateAgainstDisplay,
+};
+
+use std::fmt;
+
+use errors::*;
+
+bitflags! {
+    /// IOKit display mode flags, as returned by `CGDisplayMode::io_flags`.
+    pub struct ModeFlags: u32 {
+        const VALID = kDisplayModeValidFlag;
+        const SAFE = kDisplayModeSafeFlag;
+        const DEFAULT = kDisplayModeDefaultFlag;
+        const ALWAYS_SHOW = kDisplayModeAlwaysShowFlag;
+        const NOT_RESIZE = kDisplayModeNotResizeFlag;
+        const REQUIRES_PAN = kDisplayModeRequiresPanFlag;
+        const INTERLACED = kDisplayModeInterlacedFlag;
+        const NEVER_SHOW = kDisplayModeNeverShowFlag;
+        const SIMULSCAN = kDisplayModeSimulscanFlag;
+        const NOT_PRESET = kDisplayModeNotPresetFlag;
+        const BUILTIN = kDisplayModeBuiltInFlag;
+        const STRETCHED = kDisplayModeStretchedFlag;
+        const NOT_GRAPHICS_QUALITY = kDisplayModeNotGraphicsQualityFlag;
+        const VALIDATE_AGAINST_DISPLAY = kDisplayModeValidateAgainstDisplay;
+        const TV = kDisplayModeTelevisionFlag;
+        const VALID_FOR_MIRRORING = kDisplayModeValidForMirroringFlag;
+        const ACCELERATOR_BACKED = kDisplayModeAcceleratorBackedFlag;
+        const VALID_FOR_HIRES = kDisplayModeValidForHiResFlag;
+        const VALID_FOR_AIRPLAY = kDisplayModeValidForAirPlayFlag;
+        const NATIVE = kDisplayModeNativeFlag;
+    }
+}
+
+/// Names of the flags, as shown by `list --long` and accepted by `list --flag`.
+pub const FLAG_NAMES: &[(ModeFlags, &str)] = &[
+    (ModeFlags::VALID, "valid"),
+    (ModeFlags::SAFE, "safe"),
+    (ModeFlags::DEFAULT, "default"),
+    (ModeFlags::ALWAYS_SHOW, "always-show"),
+    (ModeFlags::NOT_RESIZE, "not-resize"),
+    (ModeFlags::REQUIRES_PAN, "requires-pan"),
+    (ModeFlags::INTERLACED, "interlaced"),
+    (ModeFlags::NEVER_SHOW, "never-show"),
+    (ModeFlags::SIMULSCAN, "simulscan"),
+    (ModeFlags::NOT_PRESET, "not-preset"),
+    (ModeFlags::BUILTIN, "builtin"),
+    (ModeFlags::STRETCHED, "stretched"),
+    (ModeFlags::NOT_GRAPHICS_QUALITY, "not-graphics-quality"),
+    (
+        ModeFlags::VALIDATE_AGAINST_DISPLAY,
+        "validate-against-display",
+    ),
+    (ModeFlags::TV, "tv"),
+    (ModeFlags::VALID_FOR_MIRRORING, "mirroring"),
+    (ModeFlags::ACCELERATOR_BACKED, "accelerator-backed"),
+    (ModeFlags::VALID_FOR_HIRES, "hires"),
+    (ModeFlags::VALID_FOR_AIRPLAY, "airplay"),
+    (ModeFlags::NATIVE, "native"),
+];
+
+impl ModeFlags {
+    /// The flag with that name, ignoring case.
+    pub fn from_name(name: &str) -> Option<ModeFlags> {
+        FLAG_NAMES
+            .iter()
+            .find(|&&(_, flag_name)| flag_name.eq_ignore_ascii_case(name))
+            .map(|&(flag, _)| flag)
+    }
+
+    /// The flags with those names, or an error naming the first unknown one.
+    pub fn from_names<'a, I>(names: I) -> Result<ModeFlags>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut flags = ModeFlags::empty();
+        for name in names {
+            flags |= ModeFlags::from_name(name)
+                .ok_or_else(|| format!("Not a known mode flag: {}", name))?;
+        }
+        Ok(flags)
+    }
+}
+
+/// The names of the flags separated by spaces, `none` when no flag is set.
+impl fmt::Display for ModeFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = FLAG_NAMES
+            .iter()
+            .filter(|&&(flag, _)| self.contains(flag))
+            .map(|&(_, name)| name)
+            .collect();
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(" "))
+        }
+    }
+}
+
+/// The flags as words, followed by the bits that have no name in hexadecimal.
+pub fn describe(io_flags: u32) -> String {
+    let unknown = io_flags & !ModeFlags::all().bits();
+    let flags = ModeFlags::from_bits_truncate(io_flags);
+    if unknown == 0 {
+        flags.to_string()
+    } else if flags.is_empty() {
+        format!("0x{:07X}", unknown)
+    } else {
+        format!("{} 0x{:07X}", flags, unknown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_names_cover_all_flags() {
+        let named = FLAG_NAMES
+            .iter()
+            .fold(ModeFlags::empty(), |flags, &(flag, _)| flags | flag);
+
+        assert_eq!(ModeFlags::all(), named);
+    }
+
+    #[test]
+    fn from_names_known_and_unknown() {
+        assert_eq!(
+            ModeFlags::NATIVE | ModeFlags::SAFE,
+            ModeFlags::from_names(vec!["native", "Safe"]).unwrap()
+        );
+        assert_eq!(true, ModeFlags::from_names(vec!["native", "wide"]).is_err());
+    }
+
+    #[test]
+    fn describe_flags() {
+        assert_eq!("valid safe native", describe(0x2000003));
+        assert_eq!("none", describe(0));
+        assert_eq!("valid 0x4000000", describe(0x4000001));
+    }
+}
@@ -0,0 +1,252 @@
+use chrono::{DateTime, FixedOffset, Local};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use errors::*;
+use mode::DisplayIndex;
+use paths;
+
+/// A successful mode change, as recorded in the history file.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub timestamp: DateTime<FixedOffset>,
+    /// Identity of the display that survives reconnections and reboots, see
+    /// `ScreenResolution::display_identity`.
+    pub display: String,
+    pub display_index: DisplayIndex,
+    pub previous: String,
+    pub new: String,
+}
+
+impl Change {
+    pub fn now(
+        display: String,
+        display_index: DisplayIndex,
+        previous: String,
+        new: String,
+    ) -> Change {
+        let now = Local::now();
+        Change {
+            timestamp: now.with_timezone(now.offset()),
+            display,
+            display_index,
+            previous,
+            new,
+        }
+    }
+
+    /// Parse a history line: tab separated timestamp, display identity, display index, previous
+    /// mode and new mode.
+    fn from_line(line: &str) -> Result<Change> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 5 {
+            return Err(format!("Not a valid history line: {}", line).into());
+        }
+        Ok(Change {
+            timestamp: DateTime::parse_from_rfc3339(fields[0])
+                .chain_err(|| format!("Not a valid history timestamp: {}", fields[0]))?,
+            display: fields[1].to_string(),
+            display_index: fields[2]
+                .parse()
+                .chain_err(|| format!("Not a valid history display: {}", fields[2]))?,
+            previous: fields[3].to_string(),
+            new: fields[4].to_string(),
+        })
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.timestamp.to_rfc3339(),
+            self.display,
+            self.display_index,
+            self.previous,
+            self.new
+        )
+    }
+
+    pub fn print_change(&self, output: &mut io::Write) -> Result<()> {
+        write!(
+            output,
+            "{} Display {} ({}): {} -> {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.display_index,
+            self.display,
+            self.previous,
+            self.new
+        ).chain_err(|| "Could not print change")?;
+        Ok(())
+    }
+}
+
+/// The list of changes made with `set`, oldest first, kept in a file.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> History {
+        History { path }
+    }
+
+    /// The history kept in the tool's own directory.
+    pub fn open() -> Result<History> {
+        Ok(History::new(paths::app_dir()?.join("history")))
+    }
+
+    pub fn record(&self, change: &Change) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).chain_err(|| "Could not create history directory")?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .chain_err(|| "Could not open history file")?;
+        writeln!(file, "{}", change.to_line()).chain_err(|| "Could not write history file")?;
+        Ok(())
+    }
+
+    pub fn changes(&self) -> Result<Vec<Change>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = File::open(&self.path).chain_err(|| "Could not open history file")?;
+        let mut changes = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.chain_err(|| "Could not read history file")?;
+            if !line.is_empty() {
+                changes.push(Change::from_line(&line)?);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Drop the changes of the display that come after the `start` oldest changes, once they
+    /// are undone.
+    pub fn drop_changes(&self, start: usize, display: &str) -> Result<()> {
+        let changes = self.changes()?;
+        let mut file = File::create(&self.path).chain_err(|| "Could not write history file")?;
+        for (i, change) in changes.iter().enumerate() {
+            if i < start || change.display != display {
+                writeln!(file, "{}", change.to_line())
+                    .chain_err(|| "Could not write history file")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The mode each display must be set to in order to revert the last `count` changes, as pairs
+/// of display identity and mode. Only the oldest reverted change of a display matters.
+pub fn undo_targets(changes: &[Change], count: usize) -> Vec<(String, String)> {
+    let mut targets: Vec<(String, String)> = Vec::new();
+    for change in changes.iter().rev().take(count) {
+        match targets
+            .iter()
+            .position(|&(ref display, _)| *display == change.display)
+        {
+            Some(position) => targets[position].1 = change.previous.clone(),
+            None => targets.push((change.display.clone(), change.previous.clone())),
+        }
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn test_change(display: &str, previous: &str, new: &str) -> Change {
+        Change {
+            timestamp: DateTime::parse_from_rfc3339("2018-09-17T10:20:30+02:00").unwrap(),
+            display: display.to_string(),
+            display_index: 0,
+            previous: previous.to_string(),
+            new: new.to_string(),
+        }
+    }
+
+    #[test]
+    fn change_line_round_trip() {
+        let change = test_change("0610:a050:00000000", "1440x900x32@0", "1920x1200x32@0");
+
+        let line = change.to_line();
+
+        assert_eq!(
+            "2018-09-17T10:20:30+02:00\t0610:a050:00000000\t0\t1440x900x32@0\t1920x1200x32@0",
+            line
+        );
+        assert_eq!(change, Change::from_line(&line).unwrap());
+    }
+
+    #[test]
+    fn change_from_invalid_line() {
+        assert_eq!(true, Change::from_line("1440x900x32@0").is_err());
+    }
+
+    #[test]
+    fn print_change() {
+        let change = test_change("0610:a050:00000000", "1440x900x32@0", "1920x1200x32@0");
+        let mut vec = Vec::<u8>::new();
+
+        change
+            .print_change(&mut vec)
+            .expect("Error while testing print_change");
+
+        assert_eq!(
+            "2018-09-17 10:20:30 Display 0 (0610:a050:00000000): 1440x900x32@0 -> 1920x1200x32@0",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn history_record_and_drop_changes() {
+        let path = env::temp_dir().join(format!(
+            "screenresolution-rs-history-{}",
+            ::std::process::id()
+        ));
+        let history = History::new(path.clone());
+        let first = test_change("a", "1440x900x32@0", "1920x1200x32@0");
+        let second = test_change("b", "1024x768x32@0", "800x600x32@0");
+        let third = test_change("a", "1920x1200x32@0", "1280x800x32@0");
+
+        history.record(&first).unwrap();
+        history.record(&second).unwrap();
+        history.record(&third).unwrap();
+        assert_eq!(
+            vec![&first, &second, &third],
+            history.changes().unwrap().iter().collect::<Vec<_>>()
+        );
+
+        history.drop_changes(1, "a").unwrap();
+        assert_eq!(vec![first, second], history.changes().unwrap());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn undo_targets_oldest_previous_per_display() {
+        let changes = vec![
+            test_change("a", "640x480x32@0", "800x600x32@0"),
+            test_change("a", "800x600x32@0", "1024x768x32@0"),
+            test_change("b", "1440x900x32@0", "1920x1200x32@0"),
+            test_change("a", "1024x768x32@0", "1280x800x32@0"),
+        ];
+
+        assert_eq!(
+            vec![("a".to_string(), "1024x768x32@0".to_string())],
+            undo_targets(&changes, 1)
+        );
+        assert_eq!(
+            vec![
+                ("a".to_string(), "800x600x32@0".to_string()),
+                ("b".to_string(), "1440x900x32@0".to_string()),
+            ],
+            undo_targets(&changes, 3)
+        );
+    }
+}
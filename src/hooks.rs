@@ -0,0 +1,139 @@
+use std::process::Command;
+
+use errors::*;
+use mode::DisplayIndex;
+
+/// External commands run around every mode change. They are run with `sh -c` and receive the
+/// change through `SCREENRESOLUTION_*` environment variables, see `HookEnv`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Hooks {
+    /// Run before the change, a non-zero exit status vetoes the change.
+    pub pre: Option<String>,
+    /// Run after the change was applied.
+    pub post: Option<String>,
+}
+
+/// What the hooks are told about a mode change.
+pub struct HookEnv {
+    pub display: DisplayIndex,
+    pub display_id: u32,
+    pub identity: String,
+    pub old_mode: String,
+    pub new_mode: String,
+}
+
+impl HookEnv {
+    fn vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("SCREENRESOLUTION_DISPLAY", self.display.to_string()),
+            ("SCREENRESOLUTION_DISPLAY_ID", self.display_id.to_string()),
+            ("SCREENRESOLUTION_DISPLAY_IDENTITY", self.identity.clone()),
+            ("SCREENRESOLUTION_OLD_MODE", self.old_mode.clone()),
+            ("SCREENRESOLUTION_NEW_MODE", self.new_mode.clone()),
+        ]
+    }
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre.is_none() && self.post.is_none()
+    }
+
+    pub fn run_pre(&self, env: &HookEnv) -> Result<()> {
+        match self.pre {
+            Some(ref command) => run_hook(command, env)
+                .map_err(|status| ErrorKind::HookVetoed(command.clone(), status).into()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn run_post(&self, env: &HookEnv) -> Result<()> {
+        match self.post {
+            Some(ref command) => run_hook(command, env)
+                .map_err(|status| format!("Post-hook `{}` failed ({})", command, status).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Run a hook command, returning a description of its exit status when it did not succeed.
+fn run_hook(command: &str, env: &HookEnv) -> ::std::result::Result<(), String> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.vars())
+        .status()
+        .map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(status.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_env() -> HookEnv {
+        HookEnv {
+            display: 1,
+            display_id: 188940995,
+            identity: "10ac:a0c4:4c4a3035".to_string(),
+            old_mode: "1440x900x32@0".to_string(),
+            new_mode: "1920x1200x32@0".to_string(),
+        }
+    }
+
+    #[test]
+    fn run_pre_receives_change() {
+        let hooks = Hooks {
+            pre: Some(
+                "test \"$SCREENRESOLUTION_DISPLAY\" = 1 \
+                 && test \"$SCREENRESOLUTION_DISPLAY_ID\" = 188940995 \
+                 && test \"$SCREENRESOLUTION_DISPLAY_IDENTITY\" = 10ac:a0c4:4c4a3035 \
+                 && test \"$SCREENRESOLUTION_OLD_MODE\" = 1440x900x32@0 \
+                 && test \"$SCREENRESOLUTION_NEW_MODE\" = 1920x1200x32@0"
+                    .to_string(),
+            ),
+            post: None,
+        };
+
+        assert_eq!(true, hooks.run_pre(&test_env()).is_ok());
+    }
+
+    #[test]
+    fn run_pre_vetoes() {
+        let hooks = Hooks {
+            pre: Some("exit 3".to_string()),
+            post: None,
+        };
+
+        let error = hooks.run_pre(&test_env()).unwrap_err();
+
+        assert_eq!(EXIT_HOOK_VETOED, error.exit_code());
+        assert_eq!(
+            "Pre-hook `exit 3` vetoed the change (exit status: 3)",
+            format!("{}", error)
+        );
+    }
+
+    #[test]
+    fn run_post_fails() {
+        let hooks = Hooks {
+            pre: None,
+            post: Some("false".to_string()),
+        };
+
+        assert_eq!(true, hooks.run_post(&test_env()).is_err());
+    }
+
+    #[test]
+    fn no_hooks() {
+        let hooks = Hooks::default();
+
+        assert_eq!(true, hooks.is_empty());
+        assert_eq!(true, hooks.run_pre(&test_env()).is_ok());
+        assert_eq!(true, hooks.run_post(&test_env()).is_ok());
+    }
+}
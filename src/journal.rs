@@ -0,0 +1,110 @@
+use record::Record;
+
+/// A display configuration about to be made, one per display it changes. It is written before
+/// configuring the displays and removed once done, so finding it afterwards means that the tool
+/// died in the middle of it.
+#[derive(Debug, PartialEq)]
+pub struct JournalEntry {
+    /// Process making the change.
+    pub pid: u32,
+    /// Identity of the display, see `ScreenResolution::display_identity`.
+    pub display: String,
+    pub previous: String,
+    pub new: String,
+}
+
+impl Record for JournalEntry {
+    const FILE_NAME: &'static str = "journal";
+    const NAME: &'static str = "journal entry";
+    const FIELDS: usize = 3;
+
+    fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    fn fields(&self) -> Vec<&str> {
+        vec![&self.display, &self.previous, &self.new]
+    }
+
+    fn from_fields(pid: u32, fields: &[&str]) -> JournalEntry {
+        JournalEntry {
+            pid,
+            display: fields[0].to_string(),
+            previous: fields[1].to_string(),
+            new: fields[2].to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    #[test]
+    fn journal_entry_round_trip() {
+        let path = env::temp_dir().join(format!(
+            "screenresolution-rs-journal-{}",
+            ::std::process::id()
+        ));
+        let entry = JournalEntry {
+            pid: 42,
+            display: "0610:a050:00000000".to_string(),
+            previous: "1440x900x32@0".to_string(),
+            new: "1920x1200x32@0".to_string(),
+        };
+
+        entry.save(&path).unwrap();
+        assert_eq!(Some(entry), JournalEntry::load(&path).unwrap());
+
+        JournalEntry::clear(&path).unwrap();
+        assert_eq!(None, JournalEntry::load(&path).unwrap());
+    }
+
+    #[test]
+    fn journal_entries_round_trip() {
+        let path = env::temp_dir().join(format!(
+            "screenresolution-rs-journal-entries-{}",
+            ::std::process::id()
+        ));
+        let entries = vec![
+            JournalEntry {
+                pid: 42,
+                display: "0610:a050:00000000".to_string(),
+                previous: "1440x900x32@0".to_string(),
+                new: "1920x1080x32@0".to_string(),
+            },
+            JournalEntry {
+                pid: 42,
+                display: "10ac:a0c4:4c4a3035".to_string(),
+                previous: "2560x1440x32@60".to_string(),
+                new: "1920x1080x32@60".to_string(),
+            },
+        ];
+
+        JournalEntry::save_all(&entries, &path).unwrap();
+        let loaded = JournalEntry::load_all(&path);
+
+        JournalEntry::clear(&path).unwrap();
+        assert_eq!(entries, loaded.unwrap());
+    }
+
+    #[test]
+    fn journal_entry_invalid() {
+        let path = env::temp_dir().join(format!(
+            "screenresolution-rs-journal-invalid-{}",
+            ::std::process::id()
+        ));
+        File::create(&path)
+            .unwrap()
+            .write_all(b"42\t1440x900x32@0\n")
+            .unwrap();
+
+        let result = JournalEntry::load(&path);
+
+        fs::remove_file(path).unwrap();
+        assert_eq!(true, result.is_err());
+    }
+}
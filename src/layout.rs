@@ -0,0 +1,244 @@
+use core_graphics::base;
+use core_graphics::display::{CGDirectDisplayID, CGDisplay, CGDisplayConfigRef};
+use std::fmt;
+use std::str::FromStr;
+
+use configuration;
+use errors::*;
+use mode::DisplayIndex;
+
+/// Upper-left corner of a display in the global desktop space, in points. The main display is at
+/// 0,0 and y grows downwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Origin {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Origin {
+    /// The origin CoreGraphics reports for a display.
+    pub fn of_display(display_id: CGDirectDisplayID) -> Origin {
+        let bounds = CGDisplay::new(display_id).bounds();
+        Origin {
+            x: bounds.origin.x.round() as i32,
+            y: bounds.origin.y.round() as i32,
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+/// Parse an origin written like `1920,0` or `-1440,-180`.
+impl FromStr for Origin {
+    type Err = Error;
+
+    fn from_str(origin: &str) -> Result<Origin> {
+        let mut parts = origin.splitn(2, ',').map(|part| part.trim().parse::<i32>());
+        match (parts.next(), parts.next()) {
+            (Some(Ok(x)), Some(Ok(y))) => Ok(Origin { x, y }),
+            _ => Err(format!("Not a valid position: {}", origin).into()),
+        }
+    }
+}
+
+/// Area a display covers in the global desktop space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub origin: Origin,
+    pub width: u64,
+    pub height: u64,
+}
+
+/// Side of the reference display a display is placed on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Relation {
+    RightOf,
+    LeftOf,
+    Above,
+    Below,
+}
+
+pub const RELATIONS: [Relation; 4] = [
+    Relation::RightOf,
+    Relation::LeftOf,
+    Relation::Above,
+    Relation::Below,
+];
+
+impl Relation {
+    /// The name of the option placing a display this way.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Relation::RightOf => "right-of",
+            Relation::LeftOf => "left-of",
+            Relation::Above => "above",
+            Relation::Below => "below",
+        }
+    }
+
+    /// The alignment with that name: top, center or bottom for displays side by side, left,
+    /// center or right for stacked displays.
+    pub fn align(&self, align: &str) -> Result<Align> {
+        let names = match *self {
+            Relation::RightOf | Relation::LeftOf => ["top", "center", "bottom"],
+            Relation::Above | Relation::Below => ["left", "center", "right"],
+        };
+        match names.iter().position(|&name| name == align) {
+            Some(0) => Ok(Align::Start),
+            Some(1) => Ok(Align::Center),
+            Some(2) => Ok(Align::End),
+            _ => Err(format!(
+                "Not a valid alignment with --{}: {}, use {}",
+                self.key(),
+                align,
+                names.join(", ")
+            ).into()),
+        }
+    }
+}
+
+/// How a display lines up with the reference display along the shared edge: top, center or
+/// bottom for displays side by side, left, center or right for stacked displays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+/// Where to move a display: next to another display, or at an origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    Next(Relation, DisplayIndex, Align),
+    At(Origin),
+}
+
+/// Offset that lines up a length with a reference length.
+fn aligned(start: i32, reference: u64, length: u64, align: Align) -> i32 {
+    match align {
+        Align::Start => start,
+        Align::Center => start + (reference as i32 - length as i32) / 2,
+        Align::End => start + reference as i32 - length as i32,
+    }
+}
+
+/// Origin of a display of that size placed next to the reference bounds.
+pub fn place(
+    reference: &Bounds,
+    width: u64,
+    height: u64,
+    relation: Relation,
+    align: Align,
+) -> Origin {
+    let Bounds {
+        origin,
+        width: reference_width,
+        height: reference_height,
+    } = *reference;
+    match relation {
+        Relation::RightOf => Origin {
+            x: origin.x + reference_width as i32,
+            y: aligned(origin.y, reference_height, height, align),
+        },
+        Relation::LeftOf => Origin {
+            x: origin.x - width as i32,
+            y: aligned(origin.y, reference_height, height, align),
+        },
+        Relation::Above => Origin {
+            x: aligned(origin.x, reference_width, width, align),
+            y: origin.y - height as i32,
+        },
+        Relation::Below => Origin {
+            x: aligned(origin.x, reference_width, width, align),
+            y: origin.y + reference_height as i32,
+        },
+    }
+}
+
+extern "C" {
+    fn CGConfigureDisplayOrigin(
+        config: CGDisplayConfigRef,
+        display: CGDirectDisplayID,
+        x: i32,
+        y: i32,
+    ) -> base::CGError;
+}
+
+/// Move the displays to their origins in a single display configuration.
+pub fn configure_origins(origins: &[(CGDirectDisplayID, Origin)]) -> Result<()> {
+    let display = CGDisplay::new(origins[0].0);
+    configuration::with_configuration(&display, |config_ref| {
+        for &(display_id, origin) in origins {
+            configuration::configured(unsafe {
+                CGConfigureDisplayOrigin(config_ref, display_id, origin.x, origin.y)
+            })?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFERENCE: Bounds = Bounds {
+        origin: Origin { x: 0, y: 0 },
+        width: 2560,
+        height: 1440,
+    };
+
+    #[test]
+    fn parse_origin() {
+        assert_eq!(
+            Origin { x: 1920, y: 0 },
+            "1920,0".parse::<Origin>().unwrap()
+        );
+        assert_eq!(
+            Origin { x: -1440, y: -180 },
+            "-1440,-180".parse::<Origin>().unwrap()
+        );
+        assert_eq!(true, "1920".parse::<Origin>().is_err());
+        assert_eq!("-1440,-180", format!("{}", Origin { x: -1440, y: -180 }));
+    }
+
+    #[test]
+    fn align_for_relation() {
+        assert_eq!(Align::Start, Relation::RightOf.align("top").unwrap());
+        assert_eq!(Align::End, Relation::Above.align("right").unwrap());
+        assert_eq!(true, Relation::LeftOf.align("right").is_err());
+        assert_eq!(true, Relation::Below.align("bottom").is_err());
+        assert_eq!(true, Relation::Below.align("middle").is_err());
+    }
+
+    #[test]
+    fn place_side_by_side() {
+        assert_eq!(
+            Origin { x: 2560, y: 0 },
+            place(&REFERENCE, 1440, 900, Relation::RightOf, Align::Start)
+        );
+        assert_eq!(
+            Origin { x: -1440, y: 270 },
+            place(&REFERENCE, 1440, 900, Relation::LeftOf, Align::Center)
+        );
+        assert_eq!(
+            Origin { x: 2560, y: 540 },
+            place(&REFERENCE, 1440, 900, Relation::RightOf, Align::End)
+        );
+    }
+
+    #[test]
+    fn place_stacked() {
+        assert_eq!(
+            Origin { x: 560, y: -900 },
+            place(&REFERENCE, 1440, 900, Relation::Above, Align::Center)
+        );
+        assert_eq!(
+            Origin { x: 1120, y: 1440 },
+            place(&REFERENCE, 1440, 900, Relation::Below, Align::End)
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use encoding::{ColorFormat, PixelEncoding};
+use errors::*;
+use mode::Mode;
+use refresh::RefreshRate;
+use timings::{Standard, Timings};
+
+/// Display Stream Compression is visually lossless down to a third of the uncompressed rate...
+const DSC_MAX_RATIO: u64 = 3;
+/// ...but does not go below 8 bits per pixel.
+const DSC_MIN_BITS_PER_PIXEL: u64 = 8;
+
+/// A version of a display link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Hdmi14,
+    Hdmi20,
+    Hdmi21,
+    Dp12,
+    Dp14,
+    Dp20,
+}
+
+pub const LINK_KINDS: &[LinkKind] = &[
+    LinkKind::Hdmi14,
+    LinkKind::Hdmi20,
+    LinkKind::Hdmi21,
+    LinkKind::Dp12,
+    LinkKind::Dp14,
+    LinkKind::Dp20,
+];
+
+impl LinkKind {
+    /// The name used on the command line.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            LinkKind::Hdmi14 => "hdmi1.4",
+            LinkKind::Hdmi20 => "hdmi2.0",
+            LinkKind::Hdmi21 => "hdmi2.1",
+            LinkKind::Dp12 => "dp1.2",
+            LinkKind::Dp14 => "dp1.4",
+            LinkKind::Dp20 => "dp2.0",
+        }
+    }
+
+    pub fn is_displayport(&self) -> bool {
+        match *self {
+            LinkKind::Dp12 | LinkKind::Dp14 | LinkKind::Dp20 => true,
+            LinkKind::Hdmi14 | LinkKind::Hdmi20 | LinkKind::Hdmi21 => false,
+        }
+    }
+
+    /// Data rate left for pixels over all lanes, once the line coding is taken off, in kbit/s.
+    /// HDMI before 2.1 and DisplayPort before 2.0 code 8 bits in 10, HDMI 2.1 FRL 16 in 18 and
+    /// DisplayPort 2.0 128 in 132.
+    fn capacity(&self) -> u64 {
+        match *self {
+            // 3 TMDS channels at 340 and 600 MHz
+            LinkKind::Hdmi14 => 3 * 3_400_000 * 8 / 10,
+            LinkKind::Hdmi20 => 3 * 6_000_000 * 8 / 10,
+            // 4 FRL lanes at 12 Gbit/s
+            LinkKind::Hdmi21 => 4 * 12_000_000 * 16 / 18,
+            // 4 lanes of HBR2, HBR3 and UHBR20
+            LinkKind::Dp12 => 4 * 5_400_000 * 8 / 10,
+            LinkKind::Dp14 => 4 * 8_100_000 * 8 / 10,
+            LinkKind::Dp20 => 4 * 20_000_000 * 128 / 132,
+        }
+    }
+
+    pub fn supports_dsc(&self) -> bool {
+        match *self {
+            LinkKind::Hdmi21 | LinkKind::Dp14 | LinkKind::Dp20 => true,
+            LinkKind::Hdmi14 | LinkKind::Hdmi20 | LinkKind::Dp12 => false,
+        }
+    }
+}
+
+impl fmt::Display for LinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                LinkKind::Hdmi14 => "HDMI 1.4",
+                LinkKind::Hdmi20 => "HDMI 2.0",
+                LinkKind::Hdmi21 => "HDMI 2.1",
+                LinkKind::Dp12 => "DP 1.2",
+                LinkKind::Dp14 => "DP 1.4",
+                LinkKind::Dp20 => "DP 2.0",
+            }
+        )
+    }
+}
+
+/// A display link, with the DisplayPort lanes it has. USB-C alternate mode leaves 2 of the 4
+/// lanes to DisplayPort when USB 3 data shares the cable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    pub kind: LinkKind,
+    pub lanes: u64,
+}
+
+/// How a signal fits on a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkFit {
+    Fits,
+    NeedsDsc,
+    Exceeds,
+}
+
+impl Link {
+    pub fn new(kind: LinkKind) -> Link {
+        Link { kind, lanes: 4 }
+    }
+
+    /// Data rate left for pixels, in kbit/s.
+    pub fn capacity(&self) -> u64 {
+        self.kind.capacity() * self.lanes / 4
+    }
+
+    /// How a signal with this pixel clock in kHz and these bits per pixel fits on the link.
+    pub fn fit(&self, pixel_clock: u64, bits_per_pixel: u64) -> LinkFit {
+        let data_rate = pixel_clock * bits_per_pixel;
+        let compressed_rate = (data_rate / DSC_MAX_RATIO).max(pixel_clock * DSC_MIN_BITS_PER_PIXEL);
+        if data_rate <= self.capacity() {
+            LinkFit::Fits
+        } else if self.kind.supports_dsc() && compressed_rate <= self.capacity() {
+            LinkFit::NeedsDsc
+        } else {
+            LinkFit::Exceeds
+        }
+    }
+
+    /// How a listed mode fits on the link, when its refresh rate is known. The link carries the
+    /// pixels of the mode, at the timings of CVT with reduced blanking.
+    pub fn fit_mode(&self, mode: &Mode) -> Option<LinkFit> {
+        let refresh = match mode.refresh_rate {
+            RefreshRate::Exact(millihertz) => f64::from(millihertz) / 1000.0,
+            _ => return None,
+        };
+        let timings = Timings::compute(
+            Standard::CvtReducedBlanking,
+            mode.pixel_width as u32,
+            mode.pixel_height as u32,
+            refresh,
+        ).ok()?;
+        let bits_per_pixel = match mode.pixel_encoding {
+            Some(encoding) => bits_per_pixel(&encoding),
+            None => 24,
+        };
+        Some(self.fit(u64::from(timings.pixel_clock), bits_per_pixel))
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.lanes == 4 {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{} x{}", self.kind, self.lanes)
+        }
+    }
+}
+
+/// Links are written like `dp1.4`, followed by the DisplayPort lanes when not all 4 are used:
+/// `dp1.4x2`.
+impl FromStr for Link {
+    type Err = Error;
+
+    fn from_str(link: &str) -> Result<Link> {
+        let link = link.to_lowercase();
+        let (kind, lanes) = match link.find('x') {
+            Some(position) => (&link[..position], Some(&link[position + 1..])),
+            None => (link.as_str(), None),
+        };
+        let kind = *LINK_KINDS
+            .iter()
+            .find(|known| known.key() == kind)
+            .ok_or_else(|| format!("Not a known link: {}", link))?;
+        let lanes = match lanes {
+            Some(lanes) if kind.is_displayport() => match lanes {
+                "1" => 1,
+                "2" => 2,
+                "4" => 4,
+                _ => return Err(format!("Not a valid number of lanes: {}", lanes).into()),
+            },
+            Some(_) => return Err(format!("Only DisplayPort links have lanes: {}", link).into()),
+            None => 4,
+        };
+        Ok(Link { kind, lanes })
+    }
+}
+
+impl fmt::Display for LinkFit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                LinkFit::Fits => "fits",
+                LinkFit::NeedsDsc => "needs DSC",
+                LinkFit::Exceeds => "exceeds",
+            }
+        )
+    }
+}
+
+/// Bits carried for each pixel: chroma subsampling sends 2 samples per pixel for 4:2:2 and 1.5
+/// for 4:2:0 instead of 3.
+pub fn bits_per_pixel(encoding: &PixelEncoding) -> u64 {
+    let bits = u64::from(encoding.bits_per_component);
+    match encoding.format {
+        ColorFormat::Rgb | ColorFormat::YCbCr444 => bits * 3,
+        ColorFormat::YCbCr422 => bits * 2,
+        ColorFormat::YCbCr420 => bits * 3 / 2,
+    }
+}
+
+fn gigabits(kilobits: u64) -> String {
+    format!("{:.2} Gbit/s", kilobits as f64 / 1_000_000.0)
+}
+
+/// Print how the signal of the timings fits on each link.
+pub fn print_check(
+    timings: &Timings,
+    encoding: &PixelEncoding,
+    links: &[Link],
+    output: &mut io::Write,
+) -> Result<()> {
+    let pixel_clock = u64::from(timings.pixel_clock);
+    let bits_per_pixel = bits_per_pixel(encoding);
+    writeln!(
+        output,
+        "{}x{}@{:.2} {} ({} bits per pixel), {} timings: pixel clock {:.2} MHz, data rate {}",
+        timings.h_display,
+        timings.v_display,
+        timings.wanted_refresh,
+        encoding,
+        bits_per_pixel,
+        timings.standard,
+        pixel_clock as f64 / 1000.0,
+        gigabits(pixel_clock * bits_per_pixel)
+    )?;
+    for link in links {
+        writeln!(
+            output,
+            "{:12} {:>14}: {}",
+            link.to_string(),
+            gigabits(link.capacity()),
+            link.fit(pixel_clock, bits_per_pixel)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoding(bits_per_component: u8, format: ColorFormat) -> PixelEncoding {
+        PixelEncoding {
+            bits_per_component,
+            format,
+            hdr: false,
+        }
+    }
+
+    #[test]
+    fn capacities() {
+        assert_eq!(8_160_000, Link::new(LinkKind::Hdmi14).capacity());
+        assert_eq!(14_400_000, Link::new(LinkKind::Hdmi20).capacity());
+        assert_eq!(25_920_000, Link::new(LinkKind::Dp14).capacity());
+        assert_eq!(12_960_000, "dp1.4x2".parse::<Link>().unwrap().capacity());
+    }
+
+    #[test]
+    fn parse_links() {
+        assert_eq!(Link::new(LinkKind::Hdmi21), "HDMI2.1".parse().unwrap());
+        assert_eq!(
+            Link {
+                kind: LinkKind::Dp20,
+                lanes: 2,
+            },
+            "dp2.0x2".parse().unwrap()
+        );
+        assert_eq!(true, "hdmi2.0x2".parse::<Link>().is_err());
+        assert_eq!(true, "dp1.4x3".parse::<Link>().is_err());
+        assert_eq!(true, "vga".parse::<Link>().is_err());
+    }
+
+    #[test]
+    fn uhd_at_144() {
+        let timings = Timings::compute(Standard::CvtReducedBlanking, 3840, 2160, 144.0).unwrap();
+        let pixel_clock = u64::from(timings.pixel_clock);
+        let rgb = bits_per_pixel(&encoding(8, ColorFormat::Rgb));
+        let subsampled = bits_per_pixel(&encoding(8, ColorFormat::YCbCr422));
+
+        assert_eq!(
+            LinkFit::NeedsDsc,
+            Link::new(LinkKind::Dp14).fit(pixel_clock, rgb)
+        );
+        assert_eq!(
+            LinkFit::Fits,
+            Link::new(LinkKind::Dp14).fit(pixel_clock, subsampled)
+        );
+        assert_eq!(
+            LinkFit::Exceeds,
+            Link::new(LinkKind::Dp12).fit(pixel_clock, rgb)
+        );
+        assert_eq!(
+            LinkFit::Fits,
+            Link::new(LinkKind::Dp20).fit(pixel_clock, rgb)
+        );
+    }
+
+    #[test]
+    fn uhd_at_60() {
+        let timings = Timings::compute(Standard::CvtReducedBlanking, 3840, 2160, 60.0).unwrap();
+        let pixel_clock = u64::from(timings.pixel_clock);
+
+        assert_eq!(
+            LinkFit::Exceeds,
+            Link::new(LinkKind::Hdmi14).fit(pixel_clock, 24)
+        );
+        assert_eq!(
+            LinkFit::Fits,
+            Link::new(LinkKind::Hdmi20).fit(pixel_clock, 24)
+        );
+        assert_eq!(
+            LinkFit::Exceeds,
+            Link::new(LinkKind::Hdmi20).fit(pixel_clock, 30)
+        );
+        assert_eq!(
+            LinkFit::Fits,
+            Link::new(LinkKind::Hdmi20).fit(
+                pixel_clock,
+                bits_per_pixel(&encoding(10, ColorFormat::YCbCr420))
+            )
+        );
+    }
+
+    #[test]
+    fn print_check_one_link() {
+        let timings = Timings::compute(Standard::CvtReducedBlanking, 1920, 1080, 60.0).unwrap();
+        let mut vec = Vec::<u8>::new();
+
+        print_check(
+            &timings,
+            &encoding(8, ColorFormat::Rgb),
+            &[Link::new(LinkKind::Hdmi14)],
+            &mut vec,
+        ).expect("Error while testing print_check");
+
+        assert_eq!(
+            "1920x1080@60.00 8bpc RGB (24 bits per pixel), CVT-RB timings: pixel clock 138.50 MHz, \
+             data rate 3.32 Gbit/s\n\
+             HDMI 1.4        8.16 Gbit/s: fits\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+}
@@ -1,16 +1,27 @@
 #![recursion_limit = "1024"]
 #[macro_use]
+extern crate bitflags;
+#[macro_use]
 extern crate error_chain;
 
+extern crate chrono;
 extern crate clap;
 extern crate core_foundation;
 extern crate core_graphics;
 extern crate dialoguer;
 extern crate libc;
 extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 
 use regex::Regex;
 use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
 use core_foundation::base::TCFType;
 use core_foundation::dictionary::CFDictionary;
@@ -18,30 +29,105 @@ use core_foundation::number::CFNumber;
 use core_foundation::string::CFString;
 
 use core_graphics::display::{
-    kCGDisplayShowDuplicateLowResolutionModes, kDisplayModeSafeFlag, kDisplayModeValidFlag,
-    CGConfigureOption, CGDirectDisplayID, CGDisplay, CGDisplayMode,
+    kCGDisplayShowDuplicateLowResolutionModes, CGDirectDisplayID, CGDisplay, CGDisplayMode,
 };
 
-use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
 
 use dialoguer::Select;
 
+mod config;
+mod configuration;
+mod daemon;
+mod edid;
+mod encoding;
 mod errors;
+mod events;
+mod exec;
+mod flags;
+mod history;
+mod hooks;
+mod journal;
+mod layout;
+mod link;
+mod mirroring;
 mod mode;
+mod names;
+mod paths;
+mod recommend;
+mod record;
+mod refresh;
+mod timings;
 
+use config::*;
+use daemon::*;
+use edid::Edid;
+use encoding::{ColorFormat, EncodingFilter, PixelEncoding};
 use errors::*;
+use events::*;
+use exec::*;
+use flags::ModeFlags;
+use history::*;
+use hooks::*;
+use journal::*;
+use layout::{Align, Bounds, Origin, Placement, RELATIONS};
+use link::{Link, LINK_KINDS};
 use mode::*;
+use recommend::Geometry;
+use record::Record;
+use refresh::RefreshRate;
+use timings::{Standard, Timings};
+
+/// How to check that a mode was actually applied: the active mode is read back up to
+/// `retries` more times, waiting `delay` before each new attempt.
+pub struct Verification {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+/// Which modes the OS is asked for.
+pub struct ModeListing {
+    /// Also keep the modes that are not flagged valid or safe.
+    pub include_unsafe: bool,
+    /// Also get the low resolution modes that duplicate a HiDPI one.
+    pub duplicate_low_res: bool,
+}
+
+impl Default for ModeListing {
+    fn default() -> Self {
+        ModeListing {
+            include_unsafe: false,
+            duplicate_low_res: true,
+        }
+    }
+}
 
 /// ScreenResolution struct to hold the app main state:
 /// * A vec of displays
 /// * A vec of Modes corresponding to all Modes available for all displays.
+/// * The history where successful changes are recorded, if any.
+/// * The hooks to run around mode changes.
+/// * Whether unsafe modes may be set.
+/// * Whether refresh rates must match exactly.
+/// * The link the modes are checked against in long listings, if known.
 struct ScreenResolution {
     displays: Vec<CGDirectDisplayID>,
     modes: Vec<Mode>,
+    history: Option<History>,
+    hooks: Hooks,
+    force: bool,
+    exact: bool,
+    link: Option<Link>,
+    /// Origin of each display in the global desktop space, in the order of `displays`.
+    origins: Vec<Origin>,
 }
 
 impl ScreenResolution {
     pub fn new() -> Result<Self> {
+        ScreenResolution::new_with(&ModeListing::default())
+    }
+
+    pub fn new_with(listing: &ModeListing) -> Result<Self> {
         let mut modes: Vec<Mode> = Vec::with_capacity(50);
 
         let displays = convert_result(CGDisplay::active_displays())
@@ -49,12 +135,32 @@ impl ScreenResolution {
         for (i, &display_id) in displays.iter().enumerate() {
             let current_display_mode =
                 ScreenResolution::get_current_mode_for_display(i as DisplayIndex, display_id)?;
-            ScreenResolution::all_display_modes(display_id)?
+            let edid = edid::read_edid(display_id).and_then(|edid| Edid::parse(&edid).ok());
+            let vrr_range = edid.as_ref().and_then(|edid| edid.vrr_range());
+            let hdr = edid.as_ref().map_or(false, |edid| edid.hdr);
+            let encoding_capabilities = edid.as_ref().map(|edid| edid.encoding_capabilities());
+            // CoreGraphics reports 0 when it does not know the size
+            let screen_size = CGDisplay::new(display_id).screen_size();
+            let size_mm = if screen_size.width > 0.0 && screen_size.height > 0.0 {
+                Some((
+                    screen_size.width.round() as u32,
+                    screen_size.height.round() as u32,
+                ))
+            } else {
+                edid.as_ref().and_then(|edid| edid.size_mm)
+            };
+            ScreenResolution::all_display_modes(display_id, listing.duplicate_low_res)?
                 .into_iter()
                 .for_each(|cgmode| {
-                    let io_flags = cgmode.io_flags();
-                    if (io_flags & (kDisplayModeValidFlag | kDisplayModeSafeFlag)) != 0 {
-                        let mut mode = Mode::from(i as DisplayIndex, cgmode);
+                    let mut mode = Mode::from(i as DisplayIndex, cgmode);
+                    mode.vrr_range = vrr_range;
+                    mode.size_mm = size_mm;
+                    mode.encoding_capabilities = encoding_capabilities.clone();
+                    if let Some(ref mut pixel_encoding) = mode.pixel_encoding {
+                        // HDR signals need at least 10 bits per component
+                        pixel_encoding.hdr = hdr && pixel_encoding.bits_per_component >= 10;
+                    }
+                    if listing.include_unsafe || mode.is_safe() {
                         mode.current = mode == current_display_mode;
                         modes.push(mode);
                     }
@@ -65,9 +171,99 @@ impl ScreenResolution {
                 .cmp(&(b.display))
                 .then(a.width.cmp(&(b.width)).reverse())
                 .then(a.height.cmp(&(b.height)).reverse())
+                .then(a.refresh_rate.cmp(&(b.refresh_rate)).reverse())
         });
 
-        Ok(ScreenResolution { displays, modes })
+        let origins = displays
+            .iter()
+            .map(|&display_id| Origin::of_display(display_id))
+            .collect();
+        Ok(ScreenResolution {
+            displays,
+            modes,
+            origins,
+            history: None,
+            hooks: Hooks::default(),
+            force: false,
+            exact: false,
+            // CoreGraphics does not tell how a display is connected
+            link: None,
+        })
+    }
+
+    /// Record successful changes in the specified history.
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Allow setting modes that are not flagged valid or safe.
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Only take modes with exactly the wanted refresh rate, not merely a close one.
+    pub fn with_exact(mut self, exact: bool) -> Self {
+        self.exact = exact;
+        self
+    }
+
+    /// Check the modes against the specified link in long listings.
+    pub fn with_link(mut self, link: Option<Link>) -> Self {
+        if link.is_some() {
+            self.link = link;
+        }
+        self
+    }
+
+    /// Run the specified hooks around mode changes.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Identify a display by its vendor, model and serial numbers, which unlike its id stay the
+    /// same across reconnections and reboots.
+    fn display_identity(display_id: CGDirectDisplayID) -> String {
+        let display = CGDisplay::new(display_id);
+        format!(
+            "{:04x}:{:04x}:{:08x}",
+            display.vendor_number(),
+            display.model_number(),
+            display.serial_number()
+        )
+    }
+
+    /// Find a display from its index, from a prefix of its identity or from `builtin`.
+    pub fn find_display(&self, display: &str) -> Result<DisplayIndex> {
+        if let Ok(display_index) = display.parse::<DisplayIndex>() {
+            return Ok(display_index);
+        }
+        self.displays
+            .iter()
+            .position(|&display_id| {
+                if display == "builtin" {
+                    CGDisplay::new(display_id).is_builtin()
+                } else {
+                    ScreenResolution::display_identity(display_id).starts_with(display)
+                }
+            }).map(|i| i as DisplayIndex)
+            .ok_or_else(|| ErrorKind::NoDisplayMatching(display.to_string()).into())
+    }
+
+    fn display_index_for_identity(&self, identity: &str) -> Option<DisplayIndex> {
+        self.displays
+            .iter()
+            .position(|&display_id| ScreenResolution::display_identity(display_id) == identity)
+            .map(|i| i as DisplayIndex)
+    }
+
+    fn current_mode(&self, display_index: DisplayIndex) -> Option<&Mode> {
+        self.modes
+            .iter()
+            .filter(|&mode| mode.current && mode.display == display_index)
+            .next()
     }
 
     fn get_current_mode_for_display(
@@ -94,12 +290,18 @@ impl ScreenResolution {
     }
 
     pub fn parse_wanted_mode(mode: &str, display: DisplayIndex) -> Result<Mode> {
-        // Parse: in the style of 1920x1200x32@0
-        let re = Regex::new(r"(\d+)x(\d+)x(\d+)@(\d+)").chain_err(|| "Could not compile regex")?;
+        // A name in front of the mode, like qhd@144, stands for its resolution
+        let expanded = names::expand_mode(mode);
+        let mode = expanded.as_ref().map_or(mode, |expanded| expanded.as_str());
+        // Parse: in the style of 1920x1200x32@0, or 1920x1200@0 for 32 bits, with a refresh
+        // rate like 60, 59.94 or 48-120
+        let re = Regex::new(r"(\d+)x(\d+)(?:x(\d+))?@(\d+(?:\.\d+)?(?:-\d+(?:\.\d+)?)?)")
+            .chain_err(|| "Could not compile regex")?;
         let captures = re.captures(mode);
         captures.map_or_else(
             || Err(format!("Not a valid mode: {}", mode).into()),
             |caps| {
+                let refresh_rate: RefreshRate = caps.get(4).unwrap().as_str().parse()?;
                 Ok(Mode {
                     display: display,
                     cgmode: None,
@@ -107,9 +309,13 @@ impl ScreenResolution {
                     height: caps.get(2).unwrap().as_str().parse().unwrap(),
                     pixel_width: 0,
                     pixel_height: 0,
-                    refresh_rate: caps.get(4).unwrap().as_str().parse().unwrap(),
+                    refresh_rate: refresh_rate,
+                    vrr_range: None,
+                    size_mm: None,
+                    pixel_encoding: None,
+                    encoding_capabilities: None,
                     io_flags: 0,
-                    bit_depth: caps.get(3).unwrap().as_str().parse().unwrap(),
+                    bit_depth: caps.get(3).map_or(32, |m| m.as_str().parse().unwrap()),
                     current: false,
                 })
             },
@@ -119,26 +325,12 @@ impl ScreenResolution {
     /// Do the actual display configuration with the specified mode on the spcified display_id.
     fn configure_display(cgmode: &CGDisplayMode, display_id: CGDirectDisplayID) -> Result<()> {
         let display = CGDisplay::new(display_id);
-        let config_ref = convert_result(display.begin_configuration())
-            .chain_err(|| "Could not begin configuring the display")?;
-        let result = display.configure_display_with_display_mode(&config_ref, cgmode);
-        match result {
-            Ok(()) => {
-                let result = display
-                    .complete_configuration(&config_ref, CGConfigureOption::ConfigurePermanently);
-                match result {
-                    Ok(()) => {
-                        println!("Settings applied!");
-                    }
-                    Err(e) => {
-                        println!("Error: {}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                println!("Error: {}", e);
-            }
-        };
+        configuration::with_configuration(&display, |config_ref| {
+            display
+                .configure_display_with_display_mode(&config_ref, cgmode)
+                .map_err(|e| ErrorKind::ConfigurationRejected(e.into()).into())
+        })?;
+        println!("Settings applied!");
         Ok(())
     }
 
@@ -151,43 +343,839 @@ impl ScreenResolution {
     ) -> bool {
         self.modes
             .iter()
-            .filter(|&m| m.current && m.display == display_index && mode == m)
+            .filter(|&m| m.current && m.display == display_index && m.matches(mode, self.exact))
             .next()
             .is_none()
     }
 
-    /// Set the specified current mode for the specied display.
-    pub fn set_current_mode(&self, mode: &str, display_index: DisplayIndex) -> Result<()> {
-        println!("Setting mode: {}, display: {}", mode, display_index);
+    /// Look up the wanted mode for the specified display. Returns the display id, the parsed
+    /// wanted mode and the matching available mode, if any.
+    fn find_wanted_mode(
+        &self,
+        mode: &str,
+        display_index: DisplayIndex,
+    ) -> Result<(CGDirectDisplayID, Mode, Option<&Mode>)> {
+        let (mode, encoding) = encoding::split_mode(mode);
+        let encoding: EncodingFilter = match encoding {
+            Some(encoding) => encoding.parse()?,
+            None => EncodingFilter::default(),
+        };
         let wanted_mode = ScreenResolution::parse_wanted_mode(mode, display_index)
             .chain_err(|| "Could not parse wanted mode")?;
         let display_id = self.displays.get(display_index as usize);
         if let Some(&display_id) = display_id {
-            if self.verify_current(&wanted_mode, display_index, display_id) {
-                let possible_index = self
-                    .modes
+            // The exact refresh rate is preferred to a close one, then a safe mode to an unsafe one
+            let found = self
+                .modes
+                .iter()
+                .filter(|&mode| {
+                    mode.matches(&wanted_mode, self.exact)
+                        && encoding.matches(mode.pixel_encoding.as_ref())
+                }).min_by_key(|mode| {
+                    (
+                        mode.refresh_rate != wanted_mode.refresh_rate,
+                        !mode.is_safe(),
+                    )
+                });
+            Ok((display_id, wanted_mode, found))
+        } else {
+            Err(ErrorKind::NoSuchDisplay(display_index).into())
+        }
+    }
+
+    /// Check that the mode observed on the display is the wanted one, observing again as
+    /// specified by the verification settings when it is not.
+    fn verify_applied<F>(
+        wanted_mode: &Mode,
+        verification: &Verification,
+        mut observe: F,
+    ) -> Result<()>
+    where
+        F: FnMut() -> Result<Mode>,
+    {
+        let mut observed_mode = observe()?;
+        for _ in 0..verification.retries {
+            if observed_mode == *wanted_mode {
+                break;
+            }
+            thread::sleep(verification.delay);
+            observed_mode = observe()?;
+        }
+        if observed_mode == *wanted_mode {
+            Ok(())
+        } else {
+            Err(ErrorKind::VerificationFailed(
+                wanted_mode.for_select(),
+                observed_mode.for_select(),
+                wanted_mode.display,
+            ).into())
+        }
+    }
+
+    /// Set the specified current mode for the specied display and record the change in the
+    /// history.
+    pub fn set_current_mode(
+        &self,
+        mode: &str,
+        display_index: DisplayIndex,
+        verification: Option<&Verification>,
+    ) -> Result<()> {
+        println!("Setting mode: {}, display: {}", mode, display_index);
+        self.apply_mode(mode, display_index, verification)?;
+        if let Some(ref history) = self.history {
+            if let Some(previous) = self.current_mode(display_index) {
+                let display_id = self.displays[display_index as usize];
+                history.record(&Change::now(
+                    ScreenResolution::display_identity(display_id),
+                    display_index,
+                    previous.for_select(),
+                    mode.to_string(),
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_mode(
+        &self,
+        mode: &str,
+        display_index: DisplayIndex,
+        verification: Option<&Verification>,
+    ) -> Result<()> {
+        let (display_id, wanted_mode, found) = self.find_wanted_mode(mode, display_index)?;
+        if !self.verify_current(&wanted_mode, display_index, display_id) {
+            return Err(ErrorKind::AlreadyCurrent(mode.to_string(), display_index).into());
+        }
+        let found = match found {
+            Some(found) => found,
+            None => return Err(ErrorKind::NoMatchingMode(mode.to_string(), display_index).into()),
+        };
+        if !found.is_safe() && !self.force {
+            return Err(ErrorKind::UnsafeMode(mode.to_string(), display_index).into());
+        }
+        let cgmode = match found.cgmode.as_ref() {
+            Some(cgmode) => cgmode,
+            None => return Err(ErrorKind::NoMatchingMode(mode.to_string(), display_index).into()),
+        };
+        let identity = ScreenResolution::display_identity(display_id);
+        let old_mode = self
+            .current_mode(display_index)
+            .map(|mode| mode.for_select())
+            .unwrap_or_default();
+        let hook_env = if self.hooks.is_empty() {
+            None
+        } else {
+            Some(HookEnv {
+                display: display_index,
+                display_id,
+                identity: identity.clone(),
+                old_mode: old_mode.clone(),
+                new_mode: mode.to_string(),
+            })
+        };
+        if let Some(ref hook_env) = hook_env {
+            self.hooks.run_pre(hook_env)?;
+        }
+        let journal_path = JournalEntry::path()?;
+        JournalEntry {
+            pid: std::process::id(),
+            display: identity,
+            previous: old_mode,
+            new: mode.to_string(),
+        }.save(&journal_path)?;
+        let result = ScreenResolution::configure_display(cgmode, display_id);
+        ScreenResolution::clear_journal(&journal_path, result)?;
+        if let Some(verification) = verification {
+            // The found mode is checked, its refresh rate may only be close to the wanted one
+            ScreenResolution::verify_applied(found, verification, || {
+                ScreenResolution::get_current_mode_for_display(display_index, display_id)
+            })?;
+            println!("Mode verified!");
+        }
+        if let Some(ref hook_env) = hook_env {
+            if let Err(e) = self.hooks.run_post(hook_env) {
+                eprintln!("Warning: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Print what setting the specified mode on the specified display would do, without
+    /// configuring anything.
+    pub fn plan_current_mode(
+        &self,
+        mode: &str,
+        display_index: DisplayIndex,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        let (display_id, wanted_mode, found) = self.find_wanted_mode(mode, display_index)?;
+        match found {
+            Some(found) => {
+                writeln!(
+                    output,
+                    "Would set display {} (id {}) to {} - pixel {}x{} - flags: 0x{:07X}{}{}",
+                    display_index,
+                    display_id,
+                    found.for_select(),
+                    found.pixel_width,
+                    found.pixel_height,
+                    found.io_flags,
+                    if self.verify_current(&wanted_mode, display_index, display_id) {
+                        ""
+                    } else {
+                        " (already current)"
+                    },
+                    if found.is_safe() || self.force {
+                        ""
+                    } else {
+                        " (unsafe, needs --force)"
+                    }
+                )?;
+            }
+            None => {
+                writeln!(
+                    output,
+                    "No mode matching {} on display {} (id {})",
+                    mode, display_index, display_id
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Switch adaptive sync on or off for the display. CoreGraphics reports the range through the
+    /// EDID but has no public API to switch it, so only switching off a display without it
+    /// succeeds.
+    pub fn set_vrr(&self, display_index: DisplayIndex, on: bool) -> Result<()> {
+        if display_index as usize >= self.displays.len() {
+            return Err(ErrorKind::NoSuchDisplay(display_index).into());
+        }
+        let vrr_range = self
+            .modes
+            .iter()
+            .find(|mode| mode.display == display_index)
+            .and_then(|mode| mode.vrr_range);
+        match vrr_range {
+            None if on => Err(format!(
+                "Display {} does not report a variable refresh rate range",
+                display_index
+            ).into()),
+            None => {
+                println!(
+                    "Display {} has no variable refresh rate to switch off",
+                    display_index
+                );
+                Ok(())
+            }
+            Some(vrr_range) => Err(format!(
+                "Display {} supports a variable refresh rate of {} Hz, but it cannot be switched \
+                 through CoreGraphics",
+                display_index, vrr_range
+            ).into()),
+        }
+    }
+
+    /// Show the timings a mode missing from the display would be added with. CoreGraphics only
+    /// offers the modes of the display driver and has no public API to add one, so the mode can
+    /// only be planned.
+    pub fn plan_custom_mode(
+        &self,
+        mode: &str,
+        display_index: DisplayIndex,
+        standard: Standard,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        let timings = wanted_timings(mode, standard)?;
+        if let (_, _, Some(found)) = self.find_wanted_mode(mode, display_index)? {
+            return Err(format!(
+                "Mode {} is already available on display {}",
+                found.for_select(),
+                display_index
+            ).into());
+        }
+        writeln!(
+            output,
+            "Would add mode {} to display {} with:",
+            mode, display_index
+        )?;
+        timings.print_modeline(output)
+    }
+
+    /// Rank the modes of the display by the size they give to text, see `recommend::recommend`.
+    /// The physical size and native resolution of the display can be given when it does not
+    /// report them, or reports them wrong.
+    pub fn recommend(
+        &self,
+        display_index: DisplayIndex,
+        size_mm: Option<(u32, u32)>,
+        native: Option<(u64, u64)>,
+        distance_mm: u32,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        if display_index as usize >= self.displays.len() {
+            return Err(ErrorKind::NoSuchDisplay(display_index).into());
+        }
+        let modes: Vec<&Mode> = self
+            .modes
+            .iter()
+            .filter(|mode| mode.display == display_index)
+            .collect();
+        let size_mm = size_mm
+            .or_else(|| modes.iter().filter_map(|mode| mode.size_mm).next())
+            .ok_or_else(|| {
+                format!(
+                    "The size of display {} is unknown, give it with --size",
+                    display_index
+                )
+            })?;
+        let native = native
+            .or_else(|| Geometry::native_resolution(modes.iter().cloned()))
+            .ok_or_else(|| {
+                format!(
+                    "The native resolution of display {} is unknown, give it with --native",
+                    display_index
+                )
+            })?;
+        let geometry = Geometry {
+            size_mm,
+            native,
+            distance_mm,
+        };
+        writeln!(
+            output,
+            "Display {}: {}x{} mm, native {}x{}, seen from {} cm",
+            display_index,
+            size_mm.0,
+            size_mm.1,
+            native.0,
+            native.1,
+            distance_mm / 10
+        )?;
+        recommend::print_recommendations(&recommend::recommend(modes, &geometry), output)
+    }
+
+    /// Print the modes the displays share for mirroring, see `mirroring::print_common_modes`.
+    pub fn common_modes(
+        &self,
+        display_indexes: &[DisplayIndex],
+        output: &mut io::Write,
+    ) -> Result<()> {
+        if display_indexes.len() < 2 {
+            return Err("Common modes need at least two displays".into());
+        }
+        let mut displays = Vec::with_capacity(display_indexes.len());
+        for &display_index in display_indexes {
+            if display_index as usize >= self.displays.len() {
+                return Err(ErrorKind::NoSuchDisplay(display_index).into());
+            }
+            displays.push(
+                self.modes
+                    .iter()
+                    .filter(|mode| mode.display == display_index)
+                    .collect(),
+            );
+        }
+        mirroring::print_common_modes(&displays, output)
+    }
+
+    /// The area a display covers with its current mode.
+    fn bounds(&self, display_index: DisplayIndex) -> Result<Bounds> {
+        if display_index as usize >= self.displays.len() {
+            return Err(ErrorKind::NoSuchDisplay(display_index).into());
+        }
+        let mode = self
+            .current_mode(display_index)
+            .ok_or_else(|| format!("No current mode for display: {}", display_index))?;
+        Ok(Bounds {
+            origin: self.origins[display_index as usize],
+            width: mode.width,
+            height: mode.height,
+        })
+    }
+
+    /// Print where each display is in the global desktop space.
+    pub fn print_layout(&self, output: &mut io::Write) -> Result<()> {
+        for display_index in 0..self.displays.len() as DisplayIndex {
+            let bounds = self.bounds(display_index)?;
+            writeln!(
+                output,
+                "Display {}: {}x{} at {}",
+                display_index, bounds.width, bounds.height, bounds.origin
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Move a display next to another display or to an origin.
+    pub fn arrange(
+        &self,
+        display_index: DisplayIndex,
+        placement: Placement,
+        dry_run: bool,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        let bounds = self.bounds(display_index)?;
+        let origin = match placement {
+            Placement::Next(relation, reference, align) => {
+                if reference == display_index {
+                    return Err(
+                        format!("Display {} cannot be arranged next to itself", reference).into(),
+                    );
+                }
+                layout::place(
+                    &self.bounds(reference)?,
+                    bounds.width,
+                    bounds.height,
+                    relation,
+                    align,
+                )
+            }
+            Placement::At(origin) => origin,
+        };
+        if dry_run {
+            writeln!(output, "Would move display {} to {}", display_index, origin)?;
+            return Ok(());
+        }
+        layout::configure_origins(&[(self.displays[display_index as usize], origin)])?;
+        println!("Display {} moved to {}", display_index, origin);
+        Ok(())
+    }
+
+    /// Make the target displays mirror the source display. With `common_mode`, all of them are
+    /// set to the best mode they share, see `mirroring::shared_modes`, in the same display
+    /// configuration: either all the changes are applied or none. The mode changes are recorded
+    /// in the history.
+    pub fn mirror_enable(
+        &self,
+        source: DisplayIndex,
+        targets: &[DisplayIndex],
+        common_mode: bool,
+        dry_run: bool,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        for &display_index in [source].iter().chain(targets) {
+            if display_index as usize >= self.displays.len() {
+                return Err(ErrorKind::NoSuchDisplay(display_index).into());
+            }
+        }
+        if targets.is_empty() || targets.contains(&source) {
+            return Err(format!("Display {} cannot mirror itself", source).into());
+        }
+        let displays: Vec<Vec<&Mode>> = [source]
+            .iter()
+            .chain(targets)
+            .map(|&display_index| {
+                self.modes
                     .iter()
-                    .enumerate()
-                    .filter(|(_, ref mode)| **mode == wanted_mode)
-                    .map(|(i, _)| i)
-                    .next();
-
-                if let Some(index) = possible_index {
-                    let cgmode = &self.modes.get(index).unwrap().cgmode.as_ref();
-                    ScreenResolution::configure_display(cgmode.unwrap(), display_id)
-                        .chain_err(|| "Could not actually configure display")?;
+                    .filter(|mode| mode.display == display_index)
+                    .collect()
+            }).collect();
+        let common_modes = if common_mode {
+            match mirroring::shared_modes(&displays).into_iter().next() {
+                Some(common) => common.modes,
+                None => {
+                    return Err(format!(
+                        "Displays {} and {:?} share no mode, see common-modes",
+                        source, targets
+                    ).into())
                 }
-                Ok(())
-            } else {
-                Err("Wanted Mode is already current".into())
             }
         } else {
-            Err(format!("Unable to set mode for display: {}", display_index).into())
+            Vec::new()
+        };
+        let source_id = self.displays[source as usize];
+        let target_ids: Vec<CGDirectDisplayID> = targets
+            .iter()
+            .map(|&target| self.displays[target as usize])
+            .collect();
+        if dry_run {
+            for mode in common_modes {
+                writeln!(
+                    output,
+                    "Would set display {} to {}",
+                    mode.display,
+                    mode.for_select()
+                )?;
+            }
+            for (target, target_id) in targets.iter().zip(target_ids) {
+                writeln!(
+                    output,
+                    "Would mirror display {} (id {}) on display {} (id {})",
+                    source, source_id, target, target_id
+                )?;
+            }
+            return Ok(());
+        }
+        let changed: Vec<&Mode> = common_modes
+            .into_iter()
+            .filter(|mode| !mode.current)
+            .collect();
+        let mut cgmodes = Vec::new();
+        for mode in &changed {
+            if !mode.is_safe() && !self.force {
+                return Err(ErrorKind::UnsafeMode(mode.for_select(), mode.display).into());
+            }
+            let cgmode = mode.cgmode.as_ref().ok_or_else(|| {
+                Error::from(ErrorKind::NoMatchingMode(mode.for_select(), mode.display))
+            })?;
+            cgmodes.push((self.displays[mode.display as usize], cgmode));
+        }
+        let previous: Vec<Option<String>> = changed
+            .iter()
+            .map(|mode| {
+                self.current_mode(mode.display)
+                    .map(|mode| mode.for_select())
+            }).collect();
+        let journal_path = JournalEntry::path()?;
+        if !changed.is_empty() {
+            let entries: Vec<JournalEntry> = changed
+                .iter()
+                .zip(&previous)
+                .map(|(mode, previous)| JournalEntry {
+                    pid: std::process::id(),
+                    display: ScreenResolution::display_identity(
+                        self.displays[mode.display as usize],
+                    ),
+                    previous: previous.clone().unwrap_or_default(),
+                    new: mode.for_select(),
+                }).collect();
+            JournalEntry::save_all(&entries, &journal_path)?;
+        }
+        let result = mirroring::configure_mirroring(&target_ids, Some(source_id), &cgmodes);
+        ScreenResolution::clear_journal(&journal_path, result)?;
+        println!("Display {} mirrored on {:?}", source, targets);
+        if let Some(ref history) = self.history {
+            for (mode, previous) in changed.iter().zip(previous) {
+                if let Some(previous) = previous {
+                    let display_id = self.displays[mode.display as usize];
+                    history.record(&Change::now(
+                        ScreenResolution::display_identity(display_id),
+                        mode.display,
+                        previous,
+                        mode.for_select(),
+                    ))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop mirroring on the target displays, or on all the displays that mirror another.
+    pub fn mirror_disable(
+        &self,
+        targets: &[DisplayIndex],
+        dry_run: bool,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        for &display_index in targets {
+            if display_index as usize >= self.displays.len() {
+                return Err(ErrorKind::NoSuchDisplay(display_index).into());
+            }
+        }
+        let target_ids: Vec<CGDirectDisplayID> = if targets.is_empty() {
+            self.displays
+                .iter()
+                .cloned()
+                .filter(|&display_id| CGDisplay::new(display_id).mirrors_display() != 0)
+                .collect()
+        } else {
+            targets
+                .iter()
+                .map(|&target| self.displays[target as usize])
+                .collect()
+        };
+        if target_ids.is_empty() {
+            writeln!(output, "No display is mirroring another")?;
+            return Ok(());
+        }
+        if dry_run {
+            for target_id in target_ids {
+                writeln!(output, "Would stop mirroring on display id {}", target_id)?;
+            }
+            return Ok(());
+        }
+        mirroring::configure_mirroring(&target_ids, None, &[])?;
+        println!("Mirroring stopped");
+        Ok(())
+    }
+
+    /// Revert the last `count` changes recorded in the history.
+    pub fn undo(&self, count: usize, verification: Option<&Verification>) -> Result<()> {
+        let history = self.history.as_ref().ok_or("No history available")?;
+        let changes = history.changes()?;
+        if changes.is_empty() {
+            return Err("Nothing to undo".into());
+        }
+        let count = count.min(changes.len());
+        let start = changes.len() - count;
+        for (display, mode) in undo_targets(&changes, count) {
+            self.restore_mode(&display, &mode, verification)?;
+            // Dropped as soon as the display is restored, so that the history still matches
+            // the displays when restoring another one fails
+            history.drop_changes(start, &display)?;
+        }
+        Ok(())
+    }
+
+    /// Set the display with the specified identity back to the specified mode, without recording
+    /// the change in the history.
+    fn restore_mode(
+        &self,
+        identity: &str,
+        mode: &str,
+        verification: Option<&Verification>,
+    ) -> Result<()> {
+        let display_index = self
+            .display_index_for_identity(identity)
+            .ok_or_else(|| format!("Display {} is not connected", identity))?;
+        println!("Restoring mode: {}, display: {}", mode, display_index);
+        match self.apply_mode(mode, display_index, verification) {
+            Err(Error(ErrorKind::AlreadyCurrent(_, _), _)) => {
+                println!("Mode {} is already current", mode);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Remove the journal once the display configuration is done. Failing to remove it only
+    /// warns when the configuration failed, so that its error is the one reported.
+    fn clear_journal(journal_path: &Path, configured: Result<()>) -> Result<()> {
+        match (configured, JournalEntry::clear(journal_path)) {
+            (Err(e), Err(clear_error)) => {
+                eprintln!("Warning: {}", clear_error);
+                Err(e)
+            }
+            (configured, cleared) => configured.and(cleared),
+        }
+    }
+
+    /// Tell about a display configuration that was interrupted, if any.
+    pub fn check_journal(&self) -> Result<()> {
+        for entry in JournalEntry::load_all(&JournalEntry::path()?)? {
+            if entry.is_stale() {
+                eprintln!(
+                    "Warning: changing display {} from {} to {} was interrupted, \
+                     run `recover` to set it back to {}",
+                    entry.display, entry.previous, entry.new, entry.previous
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Restore the previous modes of a display configuration that was interrupted.
+    pub fn recover(&self) -> Result<()> {
+        let path = JournalEntry::path()?;
+        let entries = JournalEntry::load_all(&path)?;
+        if entries.is_empty() {
+            println!("Nothing to recover");
+            return Ok(());
         }
+        if entries.iter().any(|entry| !entry.is_stale()) {
+            return Err("A display configuration is in progress".into());
+        }
+        for entry in &entries {
+            self.restore_mode(&entry.display, &entry.previous, None)?;
+        }
+        JournalEntry::clear(&path)
+    }
+
+    /// Restore the mode left behind by an `exec` that did not get to restore it itself. This never
+    /// fails, so that the command that comes next still runs: when the display is not connected,
+    /// the mode is kept to be restored by a later command, and when it cannot be restored, it is
+    /// dropped.
+    pub fn restore_pending(&self) -> Result<()> {
+        let path = PendingRestore::path()?;
+        match PendingRestore::load(&path)? {
+            Some(ref pending) if pending.is_stale() => {
+                if self.display_index_for_identity(&pending.display).is_none() {
+                    eprintln!(
+                        "Warning: display {} is not connected, its mode {} left behind by an \
+                         interrupted exec will be restored once it is",
+                        pending.display, pending.mode
+                    );
+                    return Ok(());
+                }
+                println!("Restoring the mode left behind by an interrupted exec");
+                if let Err(e) = self.restore_mode(&pending.display, &pending.mode, None) {
+                    eprintln!(
+                        "Warning: could not restore mode {} of display {}: {}",
+                        pending.mode, pending.display, e
+                    );
+                }
+                PendingRestore::clear(&path)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Set the specified mode while the command runs, then set the display back to its previous
+    /// mode. The mode to restore is not persisted while the mode left behind by an interrupted
+    /// exec waits for its display to be connected again, see `restore_pending`.
+    pub fn exec(&self, mode: &str, display_index: DisplayIndex, command: &[&str]) -> Result<()> {
+        let path = PendingRestore::path()?;
+        let persist = match PendingRestore::load(&path)? {
+            Some(ref pending) if !pending.is_stale() => {
+                return Err("Another exec is already running".into())
+            }
+            Some(ref pending) => {
+                eprintln!(
+                    "Warning: the mode {} of display {} left behind by an interrupted exec is \
+                     still pending, the mode will not be restored if this exec is interrupted",
+                    pending.mode, pending.display
+                );
+                false
+            }
+            None => true,
+        };
+        let display_id = *self
+            .displays
+            .get(display_index as usize)
+            .ok_or(ErrorKind::NoSuchDisplay(display_index))?;
+        let pending = PendingRestore {
+            pid: std::process::id(),
+            display: ScreenResolution::display_identity(display_id),
+            mode: self
+                .current_mode(display_index)
+                .ok_or_else(|| format!("No current mode for display: {}", display_index))?
+                .for_select(),
+        };
+        if persist {
+            pending.save(&path)?;
+        }
+        println!("Setting mode: {}, display: {}", mode, display_index);
+        match self.apply_mode(mode, display_index, None) {
+            Err(Error(ErrorKind::AlreadyCurrent(_, _), _)) => (),
+            Err(e) => {
+                if persist {
+                    PendingRestore::clear(&path)?;
+                }
+                return Err(e);
+            }
+            Ok(()) => (),
+        }
+        let code = run_command(command);
+        // The modes known so far are from before the change, look at the displays again.
+        let restored = ScreenResolution::new().and_then(|screen_resolution| {
+            screen_resolution
+                .with_hooks(self.hooks.clone())
+                .restore_mode(&pending.display, &pending.mode, None)
+        });
+        // When the mode could not be restored, it is left pending for the next command to restore
+        if persist && restored.is_ok() {
+            PendingRestore::clear(&path)?;
+        }
+        match (code?, restored) {
+            (0, restored) => restored,
+            (code, Ok(())) => Err(ErrorKind::CommandFailed(code).into()),
+            (code, Err(e)) => Err(Error::with_chain(e, ErrorKind::CommandFailed(code))),
+        }
+    }
+
+    /// Apply the first rule matching the connected displays, or only show what it would do.
+    pub fn apply_rules(
+        &self,
+        rules: &Rules,
+        config: &Config,
+        dry_run: bool,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        let connected: Vec<ConnectedDisplay> = self
+            .displays
+            .iter()
+            .enumerate()
+            .map(|(i, &display_id)| ConnectedDisplay {
+                index: i as DisplayIndex,
+                identity: ScreenResolution::display_identity(display_id),
+                builtin: CGDisplay::new(display_id).is_builtin(),
+            }).collect();
+        match rules.find_match(&connected) {
+            Some((rule, assignments)) => {
+                writeln!(output, "Applying rule: {}", rule.name)?;
+                let mut origins: Vec<(DisplayIndex, Origin)> = Vec::new();
+                for &(display_index, rule_display) in assignments.iter() {
+                    if let Some(ref position) = rule_display.position {
+                        origins.push((display_index, position.parse()?));
+                    }
+                }
+                for (display_index, rule_display) in assignments {
+                    let mode = config.resolve_mode(&rule_display.mode);
+                    if dry_run {
+                        self.plan_current_mode(mode, display_index, output)?;
+                        continue;
+                    }
+                    match self.set_current_mode(mode, display_index, None) {
+                        Err(Error(ErrorKind::AlreadyCurrent(_, _), _)) => {
+                            writeln!(output, "Mode {} is already current", mode)?
+                        }
+                        result => result?,
+                    }
+                }
+                // Positions are applied once all modes are set, as a mode change can move
+                // the other displays
+                if dry_run {
+                    for (display_index, origin) in origins {
+                        writeln!(output, "Would move display {} to {}", display_index, origin)?;
+                    }
+                } else if !origins.is_empty() {
+                    let origins: Vec<(CGDirectDisplayID, Origin)> = origins
+                        .into_iter()
+                        .map(|(display_index, origin)| {
+                            (self.displays[display_index as usize], origin)
+                        }).collect();
+                    layout::configure_origins(&origins)?;
+                }
+            }
+            None => {
+                let identities: Vec<&str> = connected
+                    .iter()
+                    .map(|display| display.identity.as_str())
+                    .collect();
+                writeln!(
+                    output,
+                    "No rule matches the connected displays: {}",
+                    identities.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the changes made with `set`, or only the ones made to the specified display.
+    pub fn print_history(
+        &self,
+        display_index: Option<DisplayIndex>,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        let history = self.history.as_ref().ok_or("No history available")?;
+        let identity = match display_index {
+            Some(display_index) => Some(ScreenResolution::display_identity(
+                *self
+                    .displays
+                    .get(display_index as usize)
+                    .ok_or(ErrorKind::NoSuchDisplay(display_index))?,
+            )),
+            None => None,
+        };
+        for change in history.changes()? {
+            if identity
+                .as_ref()
+                .map_or(false, |identity| *identity != change.display)
+            {
+                continue;
+            }
+            change.print_change(output)?;
+            writeln!(output, "")?;
+        }
+        Ok(())
     }
 
-    fn all_display_modes(display_id: CGDirectDisplayID) -> Result<Vec<CGDisplayMode>> {
-        let value = CFNumber::from(1);
+    fn all_display_modes(
+        display_id: CGDirectDisplayID,
+        duplicate_low_res: bool,
+    ) -> Result<Vec<CGDisplayMode>> {
+        let value = CFNumber::from(if duplicate_low_res { 1 } else { 0 });
         let key =
             unsafe { CFString::wrap_under_get_rule(kCGDisplayShowDuplicateLowResolutionModes) };
         let options = CFDictionary::from_CFType_pairs(&[(key.as_CFType(), value.as_CFType())]);
@@ -197,16 +1185,33 @@ impl ScreenResolution {
         )
     }
 
-    pub fn list_modes(&self, long: bool, output: &mut io::Write) -> Result<()> {
-        for mode in self.modes.iter() {
+    pub fn list_modes(
+        &self,
+        long: bool,
+        filter: &ModeFilter,
+        output: &mut io::Write,
+    ) -> Result<()> {
+        for mode in self.modes.iter().filter(|mode| filter.matches(mode)) {
             mode.print_mode(long, output)
                 .chain_err(|| "Could not list modes")?;
+            if let (true, Some(link)) = (long, self.link) {
+                if let Some(fit) = link.fit_mode(mode) {
+                    write!(output, ", {}: {}", link, fit)?;
+                }
+            }
             writeln!(output, "")?;
         }
         Ok(())
     }
 
-    pub fn set_from_list_modes(&self, long: bool, display_index: DisplayIndex) -> Result<()> {
+    pub fn set_from_list_modes(
+        &self,
+        long: bool,
+        display_index: DisplayIndex,
+        dry_run: bool,
+        verification: Option<&Verification>,
+        output: &mut io::Write,
+    ) -> Result<()> {
         let mut selections = Vec::<String>::new();
         let mut set_strings = Vec::<String>::new();
         for mode in self.modes.iter() {
@@ -227,8 +1232,16 @@ impl ScreenResolution {
             .unwrap();
         match selection {
             Some(selection) => {
-                println!("Setting mode {}", set_strings[selection]);
-                self.set_current_mode(set_strings[selection].as_str(), display_index)?;
+                if dry_run {
+                    self.plan_current_mode(set_strings[selection].as_str(), display_index, output)?;
+                } else {
+                    println!("Setting mode {}", set_strings[selection]);
+                    self.set_current_mode(
+                        set_strings[selection].as_str(),
+                        display_index,
+                        verification,
+                    )?;
+                }
             }
             _ => {
                 println!("You cancelled");
@@ -238,15 +1251,99 @@ impl ScreenResolution {
     }
 }
 
-fn run() -> Result<()> {
-    let stdout = std::io::stdout();
-    let mut output = stdout.lock();
-    let matches = App::new("MacOS Screen Resolution Tool")
-        .version(env!("CARGO_PKG_VERSION"))
-        .author("Bernard Niset")
-        .about(env!("CARGO_PKG_DESCRIPTION"))
-        .setting(AppSettings::SubcommandRequiredElseHelp)
-        .after_help(format!("Build: {} - {}", env!("GIT_COMMIT"), env!("BUILD_DATE")).as_str())
+/// Whether to show details, from the `--long` and `--short` flags or else from the configuration.
+fn is_long(matches: &ArgMatches, config: &Config) -> bool {
+    if matches.is_present("long") {
+        true
+    } else if matches.is_present("short") {
+        false
+    } else {
+        config.format() == OutputFormat::Long
+    }
+}
+
+/// The timings of a mode string, which needs a fixed refresh rate.
+fn wanted_timings(mode: &str, standard: Standard) -> Result<Timings> {
+    let (mode, _) = encoding::split_mode(mode);
+    let mode = ScreenResolution::parse_wanted_mode(mode, 0)?;
+    let refresh = match mode.refresh_rate {
+        RefreshRate::Exact(millihertz) => f64::from(millihertz) / 1000.0,
+        _ => return Err(format!("Not a fixed refresh rate: {}", mode.refresh_rate).into()),
+    };
+    Timings::compute(standard, mode.width as u32, mode.height as u32, refresh)
+}
+
+/// A resolution at 60 Hz unless it has a refresh rate.
+fn with_default_refresh(resolution: &str) -> String {
+    if resolution.contains('@') {
+        resolution.to_string()
+    } else {
+        format!("{}@60", resolution)
+    }
+}
+
+fn print_modeline(matches: &ArgMatches, config: &Config, output: &mut io::Write) -> Result<()> {
+    let standard: Standard = matches.value_of("standard").unwrap_or("cvt").parse()?;
+    let resolution =
+        with_default_refresh(config.resolve_mode(matches.value_of("resolution").unwrap()));
+    wanted_timings(&resolution, standard)?.print_modeline(output)
+}
+
+fn check_link(matches: &ArgMatches, config: &Config, output: &mut io::Write) -> Result<()> {
+    let standard: Standard = matches.value_of("standard").unwrap_or("cvt-rb").parse()?;
+    let (resolution, encoding) =
+        encoding::split_mode(config.resolve_mode(matches.value_of("resolution").unwrap()));
+    let encoding: EncodingFilter = match encoding {
+        Some(encoding) => encoding.parse()?,
+        None => EncodingFilter::default(),
+    };
+    let encoding = PixelEncoding {
+        bits_per_component: encoding.bits_per_component.unwrap_or(8),
+        format: encoding.format.unwrap_or(ColorFormat::Rgb),
+        hdr: encoding.hdr.unwrap_or(false),
+    };
+    let timings = wanted_timings(&with_default_refresh(resolution), standard)?;
+    let links = match matches.value_of("link") {
+        Some(link) => vec![link.parse()?],
+        None => LINK_KINDS.iter().map(|&kind| Link::new(kind)).collect(),
+    };
+    link::print_check(&timings, &encoding, &links, output)
+}
+
+/// The verification asked for with `--verify` and `--no-verify`, or in the `[set]` section of
+/// the configuration.
+fn verification(matches: &ArgMatches, config: &Config) -> Result<Option<Verification>> {
+    let verify =
+        (matches.is_present("verify") || config.verify()) && !matches.is_present("no-verify");
+    if !verify {
+        return Ok(None);
+    }
+    Ok(Some(Verification {
+        retries: match matches.value_of("verify-retries") {
+            Some(retries) => retries
+                .parse()
+                .chain_err(|| "Not a valid number of retries")?,
+            None => config.verify_retries(),
+        },
+        delay: Duration::from_millis(match matches.value_of("verify-delay") {
+            Some(delay) => delay.parse().chain_err(|| "Not a valid delay")?,
+            None => config.verify_delay(),
+        }),
+    }))
+}
+
+/// Commands that change modes, and so look for an interrupted display configuration first.
+const MODE_CHANGING_COMMANDS: &[&str] = &["set", "exec", "undo", "daemon", "mirror"];
+
+fn run() -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut output = stdout.lock();
+    let matches = App::new("MacOS Screen Resolution Tool")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Bernard Niset")
+        .about(env!("CARGO_PKG_DESCRIPTION"))
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .after_help(format!("Build: {} - {}", env!("GIT_COMMIT"), env!("BUILD_DATE")).as_str())
         .subcommand(
             SubCommand::with_name("list")
                 .about("List available resolutions for current display")
@@ -257,6 +1354,61 @@ fn run() -> Result<()> {
                         .help("Shows more details on the displayed resolutions")
                         .required(false)
                         .takes_value(false),
+                ).arg(
+                    Arg::with_name("short")
+                        .long("short")
+                        .short("s")
+                        .help("Shows the displayed resolutions on one line each")
+                        .conflicts_with("long")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("Only shows the resolutions with that name (e.g.: qhd, 4k)")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("link")
+                        .long("link")
+                        .value_name("LINK")
+                        .help("Checks the resolutions against that link in long listings (e.g.: hdmi2.0, dp1.4, dp1.4x2)")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("encoding")
+                        .long("encoding")
+                        .value_name("ENCODING")
+                        .help("Only shows the resolutions with that pixel encoding, or on a display that takes it (e.g.: 10bpc, 10bpc:hdr, ycbcr422)")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .visible_alias("include-unsafe")
+                        .help("Also shows the resolutions that are not marked safe for the display")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("no-duplicate-low-res")
+                        .long("no-duplicate-low-res")
+                        .help("Hides the low resolutions that duplicate a HiDPI one")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("flag")
+                        .long("flag")
+                        .value_name("FLAG")
+                        .help("Only shows the resolutions with that flag (e.g.: native, safe)")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("no-flag")
+                        .long("no-flag")
+                        .value_name("FLAG")
+                        .help("Only shows the resolutions without that flag (e.g.: interlaced)")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
                 ),
         ).subcommand(
             SubCommand::with_name("get")
@@ -268,6 +1420,360 @@ fn run() -> Result<()> {
                         .help("Shows more details on the current resolution")
                         .required(false)
                         .takes_value(false),
+                ).arg(
+                    Arg::with_name("short")
+                        .long("short")
+                        .short("s")
+                        .help("Shows the current resolution on one line")
+                        .conflicts_with("long")
+                        .required(false)
+                        .takes_value(false),
+                ),
+        ).subcommand(
+            SubCommand::with_name("history")
+                .about("List the resolution changes made with set")
+                .arg(
+                    Arg::with_name("display")
+                        .long("display")
+                        .value_name("DISPLAY")
+                        .short("d")
+                        .help("Only lists the changes made to this display")
+                        .takes_value(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("undo")
+                .about("Revert the last resolution changes made with set")
+                .arg(
+                    Arg::with_name("count")
+                        .value_name("COUNT")
+                        .help("Number of changes to revert (default: 1)")
+                        .required(false)
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .help("Checks that the resolutions were actually restored")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("no-verify")
+                        .long("no-verify")
+                        .help("Does not check that the resolutions were restored")
+                        .conflicts_with("verify")
+                        .required(false)
+                        .takes_value(false),
+                ),
+        ).subcommand(
+            SubCommand::with_name("watch")
+                .about("Print display changes as they happen")
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Prints one JSON object per line")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("poll")
+                        .long("poll")
+                        .value_name("MILLISECONDS")
+                        .help("Looks for changes at this interval instead of being notified")
+                        .required(false)
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("display")
+                        .long("display")
+                        .value_name("DISPLAY")
+                        .short("d")
+                        .help("Only prints the changes of this display")
+                        .takes_value(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("daemon")
+                .about("Apply rules whenever displays are connected or disconnected")
+                .arg(
+                    Arg::with_name("rules")
+                        .long("rules")
+                        .value_name("FILE")
+                        .help("Rules file (default: daemon.toml in the application support directory)")
+                        .required(false)
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .short("n")
+                        .help("Shows which modes would be applied without applying them")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("debounce")
+                        .long("debounce")
+                        .value_name("MILLISECONDS")
+                        .help("Time without display events before applying rules (default: 2000)")
+                        .required(false)
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("poll")
+                        .long("poll")
+                        .value_name("MILLISECONDS")
+                        .help("Looks for changes at this interval instead of being notified")
+                        .required(false)
+                        .takes_value(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("exec")
+                .about("Run a command with another resolution, restoring the current one afterwards")
+                .arg(
+                    Arg::with_name("display")
+                        .long("display")
+                        .value_name("DISPLAY")
+                        .short("d")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("mode")
+                        .long("mode")
+                        .short("m")
+                        .value_name("RESOLUTION")
+                        .help("Resolution string in the form of WxHxP@R or WxH@R, with an optional :ENCODING (e.g.: 1920x1080@60)")
+                        .required(true)
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("command")
+                        .value_name("COMMAND")
+                        .help("Command to run, with its arguments, after --")
+                        .required(true)
+                        .multiple(true)
+                        .last(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("recover")
+                .about("Restore the resolution of an interrupted display configuration"),
+        ).subcommand(
+            SubCommand::with_name("config")
+                .about("Show the configuration")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Print the effective settings, defaults included"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("modeline")
+                .about("Compute the timings of a resolution and print them as an xrandr modeline")
+                .arg(
+                    Arg::with_name("standard")
+                        .long("standard")
+                        .value_name("STANDARD")
+                        .help("Timing standard to compute with (default: cvt)")
+                        .possible_values(&["cvt", "cvt-rb", "cvt-rb2", "gtf"])
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("resolution")
+                        .value_name("RESOLUTION")
+                        .help("Resolution string in the form of WxH@R, 60 Hz when @R is left out (e.g.: 1920x1080@60, qhd@144)")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("arrange")
+                .about("Position a display in the desktop, or show where displays are")
+                .arg(
+                    Arg::with_name("display")
+                        .long("display")
+                        .value_name("DISPLAY")
+                        .short("d")
+                        .help("Display to move")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("right-of")
+                        .long("right-of")
+                        .value_name("DISPLAY")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("left-of")
+                        .long("left-of")
+                        .value_name("DISPLAY")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("above")
+                        .long("above")
+                        .value_name("DISPLAY")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("below")
+                        .long("below")
+                        .value_name("DISPLAY")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("pos")
+                        .long("pos")
+                        .value_name("X,Y")
+                        .help("Origin of the display in points, the main display being at 0,0 (e.g.: 1920,0)")
+                        .allow_hyphen_values(true)
+                        .takes_value(true),
+                ).group(
+                    ArgGroup::with_name("placement")
+                        .args(&["right-of", "left-of", "above", "below", "pos"]),
+                ).arg(
+                    Arg::with_name("align")
+                        .long("align")
+                        .value_name("ALIGN")
+                        .help("Edge to line up with the other display: top, center or bottom next to it, left, center or right above or below it (default: top or left)")
+                        .possible_values(&["top", "center", "bottom", "left", "right"])
+                        .conflicts_with("pos")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .short("n")
+                        .help("Shows what would be done without doing it"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("mirror")
+                .about("Mirror displays")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("enable")
+                        .about("Show the image of a display on others")
+                        .arg(
+                            Arg::with_name("source")
+                                .long("source")
+                                .value_name("DISPLAY")
+                                .help("Display whose image is shown")
+                                .required(true)
+                                .takes_value(true),
+                        ).arg(
+                            Arg::with_name("target")
+                                .long("target")
+                                .value_name("DISPLAY")
+                                .help("Display to show the image on, can be repeated")
+                                .required(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .takes_value(true),
+                        ).arg(
+                            Arg::with_name("common-mode")
+                                .long("common-mode")
+                                .help("First sets all displays to the best mode they share"),
+                        ).arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .short("n")
+                                .help("Shows what would be done without doing it"),
+                        ),
+                ).subcommand(
+                    SubCommand::with_name("disable")
+                        .about("Stop mirroring")
+                        .arg(
+                            Arg::with_name("target")
+                                .long("target")
+                                .value_name("DISPLAY")
+                                .help("Display to stop mirroring on, can be repeated (default: all)")
+                                .multiple(true)
+                                .number_of_values(1)
+                                .takes_value(true),
+                        ).arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .short("n")
+                                .help("Shows what would be done without doing it"),
+                        ),
+                ),
+        ).subcommand(
+            SubCommand::with_name("common-modes")
+                .about("Find the modes displays share for mirroring")
+                .arg(
+                    Arg::with_name("display")
+                        .long("display")
+                        .value_name("DISPLAY")
+                        .short("d")
+                        .help("Display to mirror, can be repeated (default: all displays)")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("recommend")
+                .about("Rank the resolutions of a display by the size they give to text")
+                .arg(
+                    Arg::with_name("display")
+                        .long("display")
+                        .value_name("DISPLAY")
+                        .short("d")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("distance")
+                        .long("distance")
+                        .value_name("CENTIMETERS")
+                        .help("Viewing distance (default: 60)")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .value_name("WxH")
+                        .help("Physical size of the image in millimeters, when the display does not report it (e.g.: 597x336)")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("native")
+                        .long("native")
+                        .value_name("WxH")
+                        .help("Native resolution of the panel, when it is not the largest mode (e.g.: 3840x2160)")
+                        .takes_value(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("check-link")
+                .about("Check which display links can carry a resolution")
+                .arg(
+                    Arg::with_name("link")
+                        .long("link")
+                        .value_name("LINK")
+                        .help("Link to check, with the DisplayPort lanes when not 4 (e.g.: hdmi2.0, dp1.4, dp1.4x2), all when left out")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("standard")
+                        .long("standard")
+                        .value_name("STANDARD")
+                        .help("Timing standard to compute with (default: cvt-rb)")
+                        .possible_values(&["cvt", "cvt-rb", "cvt-rb2", "gtf"])
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("resolution")
+                        .value_name("RESOLUTION")
+                        .help("Resolution string in the form of WxH@R[:ENCODING], 60 Hz when @R is left out (e.g.: 3840x2160@144, 3840x2160@60:10bpc:ycbcr422)")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("mode")
+                .about("Plan custom modes")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Show the generated timings a mode would be added to a display with")
+                        .arg(
+                            Arg::with_name("display")
+                                .long("display")
+                                .value_name("DISPLAY")
+                                .short("d")
+                                .takes_value(true),
+                        ).arg(
+                            Arg::with_name("standard")
+                                .long("standard")
+                                .value_name("STANDARD")
+                                .help("Timing standard to compute with (default: cvt)")
+                                .possible_values(&["cvt", "cvt-rb", "cvt-rb2", "gtf"])
+                                .takes_value(true),
+                        ).arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .short("n")
+                                .help("Shows the mode that would be added, required as CoreGraphics cannot add modes")
+                                .required(true),
+                        ).arg(
+                            Arg::with_name("resolution")
+                                .value_name("RESOLUTION")
+                                .help("Resolution string in the form of WxH@R (e.g.: 2560x1080@75)")
+                                .required(true)
+                                .takes_value(true),
+                        ),
                 ),
         ).subcommand(
             SubCommand::with_name("set")
@@ -278,10 +1784,88 @@ fn run() -> Result<()> {
                         .value_name("DISPLAY")
                         .short("d")
                         .takes_value(true),
+                ).arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .short("n")
+                        .help("Shows which mode would be applied without applying it")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("vrr")
+                        .long("vrr")
+                        .value_name("on|off")
+                        .help("Switches variable refresh rate on or off, where supported")
+                        .possible_values(&["on", "off"])
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("exact")
+                        .long("exact")
+                        .help("Only takes a resolution with exactly the wanted refresh rate (60 does not match 59.94)")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("no-exact")
+                        .long("no-exact")
+                        .help("Takes a resolution with a close refresh rate")
+                        .conflicts_with("exact")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Allows a resolution that is not marked safe for the display")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("no-force")
+                        .long("no-force")
+                        .help("Only allows a resolution marked safe for the display")
+                        .conflicts_with("force")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("idempotent")
+                        .long("idempotent")
+                        .help("Succeeds when the wanted resolution is already current")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("no-idempotent")
+                        .long("no-idempotent")
+                        .help("Fails when the wanted resolution is already current")
+                        .conflicts_with("idempotent")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("verify")
+                        .long("verify")
+                        .help("Checks that the resolution was actually applied")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("no-verify")
+                        .long("no-verify")
+                        .help("Does not check that the resolution was applied")
+                        .conflicts_with("verify")
+                        .required(false)
+                        .takes_value(false),
+                ).arg(
+                    Arg::with_name("verify-retries")
+                        .long("verify-retries")
+                        .value_name("RETRIES")
+                        .help("Number of times to check again when verification fails (default: 3)")
+                        .takes_value(true),
+                ).arg(
+                    Arg::with_name("verify-delay")
+                        .long("verify-delay")
+                        .value_name("MILLISECONDS")
+                        .help("Time to wait before checking again (default: 500)")
+                        .takes_value(true),
                 ).arg(
                     Arg::with_name("text-resolution")
                         .value_name("RESOLUTION")
-                        .help("Resolution string in the form of WxHxP@R (e.g.: 1920x1200x32@0)")
+                        .help("Resolution string in the form of WxHxP@R[:ENCODING] (e.g.: 1920x1200x32@0, 3840x2160@60:10bpc)")
                         .required(false)
                         .takes_value(true),
                 ).arg(
@@ -292,37 +1876,898 @@ fn run() -> Result<()> {
                         .required(false),
                 ).group(
                     ArgGroup::with_name("resolution")
-                        .args(&["text-resolution", "interactive-resolution"])
-                        .required(true),
+                        .args(&["text-resolution", "interactive-resolution"]),
+                ).after_help(
+                    "Exit codes: 0 success, 1 other error, 2 no such display, \
+                     3 no matching mode, 4 already current, 5 configuration rejected, \
+                     6 backend error, 7 verification failed, 8 vetoed by pre-hook, \
+                     9 unsafe mode without --force",
                 ),
         ).get_matches();
 
-    let screen_resolution = ScreenResolution::new()?;
+    let config = Config::open()?;
+    // Timings are pure computation, they do not need the displays
+    if let Some(sub_m) = matches.subcommand_matches("modeline") {
+        return print_modeline(sub_m, &config, &mut output);
+    }
+    if let Some(sub_m) = matches.subcommand_matches("check-link") {
+        return check_link(sub_m, &config, &mut output);
+    }
+    let listing = match matches.subcommand() {
+        ("list", Some(sub_m)) => ModeListing {
+            include_unsafe: sub_m.is_present("all"),
+            duplicate_low_res: !sub_m.is_present("no-duplicate-low-res"),
+        },
+        // set needs the unsafe modes to tell them apart from missing ones, see --force, and mode
+        // must not add one of them again
+        ("set", Some(_)) | ("mode", Some(_)) => ModeListing {
+            include_unsafe: true,
+            ..ModeListing::default()
+        },
+        _ => ModeListing::default(),
+    };
+    let link = match matches
+        .subcommand_matches("list")
+        .and_then(|sub_m| sub_m.value_of("link"))
+    {
+        Some(link) => Some(link.parse()?),
+        None => None,
+    };
+    let screen_resolution = ScreenResolution::new_with(&listing)?
+        .with_history(History::open()?)
+        .with_hooks(config.hooks.clone())
+        .with_force(matches.subcommand_matches("set").map_or(false, |sub_m| {
+            (sub_m.is_present("force") || config.force()) && !sub_m.is_present("no-force")
+        }))
+        .with_exact(matches.subcommand_matches("set").map_or(false, |sub_m| {
+            (sub_m.is_present("exact") || config.exact()) && !sub_m.is_present("no-exact")
+        }))
+        .with_link(link);
+    if MODE_CHANGING_COMMANDS.contains(&matches.subcommand_name().unwrap_or("")) {
+        screen_resolution.check_journal()?;
+    }
     match matches.subcommand() {
         ("list", Some(sub_m)) => {
-            let long = sub_m.is_present("long");
-            screen_resolution.list_modes(long, &mut output)
+            let long = is_long(sub_m, &config);
+            let mut filter = ModeFilter::new();
+            if let Some(name) = sub_m.value_of("name") {
+                filter.name = Some(
+                    names::by_name(name)
+                        .ok_or_else(|| format!("Not a known resolution name: {}", name))?,
+                );
+            }
+            filter.with_flags =
+                ModeFlags::from_names(sub_m.values_of("flag").into_iter().flatten())?;
+            filter.without_flags =
+                ModeFlags::from_names(sub_m.values_of("no-flag").into_iter().flatten())?;
+            if let Some(encoding) = sub_m.value_of("encoding") {
+                filter.encoding = Some(encoding.parse()?);
+            }
+            screen_resolution.list_modes(long, &filter, &mut output)
         }
         ("get", Some(sub_m)) => {
-            let long = sub_m.is_present("long");
+            let long = is_long(sub_m, &config);
             screen_resolution.print_current_mode(long, &mut output)
         }
+        ("config", Some(sub_m)) => match sub_m.subcommand_name() {
+            Some("show") => config.print_config(&mut output),
+            _ => Ok(()),
+        },
+        ("mode", Some(sub_m)) => match sub_m.subcommand() {
+            ("add", Some(sub_m)) => {
+                let display =
+                    screen_resolution
+                        .find_display(config.resolve_display(
+                            sub_m.value_of("display").unwrap_or(config.display()),
+                        ))?;
+                let standard: Standard = sub_m.value_of("standard").unwrap_or("cvt").parse()?;
+                screen_resolution.plan_custom_mode(
+                    config.resolve_mode(sub_m.value_of("resolution").unwrap()),
+                    display,
+                    standard,
+                    &mut output,
+                )
+            }
+            _ => Ok(()),
+        },
+        ("arrange", Some(sub_m)) => {
+            let placement = match sub_m.value_of("pos") {
+                Some(pos) => Some(Placement::At(pos.parse()?)),
+                None => match RELATIONS
+                    .iter()
+                    .find(|relation| sub_m.is_present(relation.key()))
+                {
+                    Some(&relation) => {
+                        let reference = screen_resolution.find_display(
+                            config.resolve_display(sub_m.value_of(relation.key()).unwrap()),
+                        )?;
+                        let align = match sub_m.value_of("align") {
+                            Some(align) => relation.align(align)?,
+                            None => Align::Start,
+                        };
+                        Some(Placement::Next(relation, reference, align))
+                    }
+                    None => None,
+                },
+            };
+            match placement {
+                Some(placement) => {
+                    let display = screen_resolution
+                        .find_display(config.resolve_display(
+                            sub_m.value_of("display").unwrap_or(config.display()),
+                        ))?;
+                    screen_resolution.arrange(
+                        display,
+                        placement,
+                        sub_m.is_present("dry-run"),
+                        &mut output,
+                    )
+                }
+                None => screen_resolution.print_layout(&mut output),
+            }
+        }
+        ("mirror", Some(sub_m)) => {
+            let find_displays = |sub_m: &ArgMatches| -> Result<Vec<DisplayIndex>> {
+                sub_m
+                    .values_of("target")
+                    .into_iter()
+                    .flatten()
+                    .map(|display| screen_resolution.find_display(config.resolve_display(display)))
+                    .collect()
+            };
+            match sub_m.subcommand() {
+                ("enable", Some(sub_m)) => {
+                    let source = screen_resolution
+                        .find_display(config.resolve_display(sub_m.value_of("source").unwrap()))?;
+                    screen_resolution.mirror_enable(
+                        source,
+                        &find_displays(sub_m)?,
+                        sub_m.is_present("common-mode"),
+                        sub_m.is_present("dry-run"),
+                        &mut output,
+                    )
+                }
+                ("disable", Some(sub_m)) => screen_resolution.mirror_disable(
+                    &find_displays(sub_m)?,
+                    sub_m.is_present("dry-run"),
+                    &mut output,
+                ),
+                _ => Ok(()),
+            }
+        }
+        ("common-modes", Some(sub_m)) => {
+            let displays = match sub_m.values_of("display") {
+                Some(displays) => displays
+                    .map(|display| screen_resolution.find_display(config.resolve_display(display)))
+                    .collect::<Result<Vec<DisplayIndex>>>()?,
+                None => (0..screen_resolution.displays.len() as DisplayIndex).collect(),
+            };
+            screen_resolution.common_modes(&displays, &mut output)
+        }
+        ("recommend", Some(sub_m)) => {
+            let display = screen_resolution.find_display(
+                config.resolve_display(sub_m.value_of("display").unwrap_or(config.display())),
+            )?;
+            let distance_mm = match sub_m.value_of("distance") {
+                Some(distance) => {
+                    distance
+                        .parse::<u32>()
+                        .chain_err(|| "Not a valid viewing distance")?
+                        * 10
+                }
+                None => recommend::REFERENCE_DISTANCE_MM,
+            };
+            let size_mm = match sub_m.value_of("size") {
+                Some(size) => Some(recommend::parse_dimensions(size)?),
+                None => None,
+            };
+            let native = match sub_m.value_of("native") {
+                Some(native) => {
+                    let (width, height) = recommend::parse_dimensions(native)?;
+                    Some((u64::from(width), u64::from(height)))
+                }
+                None => None,
+            };
+            screen_resolution.recommend(display, size_mm, native, distance_mm, &mut output)
+        }
+        ("history", Some(sub_m)) => {
+            let display = match sub_m.value_of("display") {
+                Some(display) => {
+                    Some(screen_resolution.find_display(config.resolve_display(display))?)
+                }
+                None => None,
+            };
+            screen_resolution.print_history(display, &mut output)
+        }
+        ("recover", Some(_)) => screen_resolution.recover(),
+        ("exec", Some(sub_m)) => {
+            screen_resolution.restore_pending()?;
+            let display = screen_resolution.find_display(
+                config.resolve_display(sub_m.value_of("display").unwrap_or(config.display())),
+            )?;
+            let mode = config.resolve_mode(sub_m.value_of("mode").unwrap());
+            let command: Vec<&str> = sub_m.values_of("command").unwrap().collect();
+            screen_resolution.exec(mode, display, &command)
+        }
+        ("undo", Some(sub_m)) => {
+            screen_resolution.restore_pending()?;
+            let count = sub_m
+                .value_of("count")
+                .unwrap_or("1")
+                .parse::<usize>()
+                .chain_err(|| "Not a valid number of changes")?;
+            screen_resolution.undo(count, verification(sub_m, &config)?.as_ref())
+        }
+        ("watch", Some(sub_m)) => {
+            let json = sub_m.is_present("json");
+            let display = match sub_m.value_of("display") {
+                Some(display) => {
+                    let display_index =
+                        screen_resolution.find_display(config.resolve_display(display))?;
+                    Some(
+                        *screen_resolution
+                            .displays
+                            .get(display_index as usize)
+                            .ok_or(ErrorKind::NoSuchDisplay(display_index))?,
+                    )
+                }
+                None => None,
+            };
+            match sub_m.value_of("poll") {
+                Some(interval) => {
+                    let interval = interval
+                        .parse()
+                        .chain_err(|| "Not a valid polling interval")?;
+                    let mut source = PollingEventSource::new(
+                        active_display_states,
+                        Duration::from_millis(interval),
+                    );
+                    watch(&mut source, json, display, &mut output)
+                }
+                None => {
+                    let mut source = ReconfigurationEventSource::new()?;
+                    watch(&mut source, json, display, &mut output)
+                }
+            }
+        }
+        ("daemon", Some(sub_m)) => {
+            screen_resolution.restore_pending()?;
+            let rules_path = match sub_m.value_of("rules") {
+                Some(rules_path) => PathBuf::from(rules_path),
+                None => paths::app_dir()?.join("daemon.toml"),
+            };
+            let dry_run = sub_m.is_present("dry-run");
+            let debounce = Duration::from_millis(
+                sub_m
+                    .value_of("debounce")
+                    .unwrap_or("2000")
+                    .parse()
+                    .chain_err(|| "Not a valid debounce delay")?,
+            );
+            let apply = |rules: &Rules, output: &mut io::Write| {
+                let config = Config::open()?;
+                ScreenResolution::new()?
+                    .with_history(History::open()?)
+                    .with_hooks(config.hooks.clone())
+                    .apply_rules(rules, &config, dry_run, output)
+            };
+            reload_on_sighup();
+            match sub_m.value_of("poll") {
+                Some(interval) => {
+                    let interval = interval
+                        .parse()
+                        .chain_err(|| "Not a valid polling interval")?;
+                    let mut source = PollingEventSource::new(
+                        active_display_states,
+                        Duration::from_millis(interval),
+                    );
+                    daemon::run(&mut source, debounce, &rules_path, &mut output, apply)
+                }
+                None => {
+                    let mut source = ReconfigurationEventSource::new()?;
+                    daemon::run(&mut source, debounce, &rules_path, &mut output, apply)
+                }
+            }
+        }
         ("set", Some(sub_m)) => {
-            let display = sub_m
-                .value_of("display")
-                .unwrap_or("0")
-                .parse::<DisplayIndex>()
-                .unwrap_or(0);
-            if sub_m.value_of("text-resolution").is_some() {
-                screen_resolution.set_current_mode(sub_m.value_of("resolution").unwrap(), display)
+            screen_resolution.restore_pending()?;
+            let display = screen_resolution.find_display(
+                config.resolve_display(sub_m.value_of("display").unwrap_or(config.display())),
+            )?;
+            let dry_run = sub_m.is_present("dry-run");
+            let verification = verification(sub_m, &config)?;
+            if let Some(vrr) = sub_m.value_of("vrr") {
+                if dry_run {
+                    println!(
+                        "Would switch variable refresh rate {} on display {}",
+                        vrr, display
+                    );
+                } else {
+                    screen_resolution.set_vrr(display, vrr == "on")?;
+                }
+            }
+            let result = if sub_m.value_of("text-resolution").is_some() {
+                let mode = config.resolve_mode(sub_m.value_of("resolution").unwrap());
+                if dry_run {
+                    screen_resolution.plan_current_mode(mode, display, &mut output)
+                } else {
+                    screen_resolution.set_current_mode(mode, display, verification.as_ref())
+                }
             } else if sub_m.is_present("interactive-resolution") {
-                screen_resolution.set_from_list_modes(false, display)
+                screen_resolution.set_from_list_modes(
+                    config.format() == OutputFormat::Long,
+                    display,
+                    dry_run,
+                    verification.as_ref(),
+                    &mut output,
+                )
+            } else if sub_m.is_present("vrr") {
+                Ok(())
             } else {
-                Err("Not a valid option".into())
+                Err("Nothing to set: give a resolution, --interactive or --vrr".into())
+            };
+            let idempotent = (sub_m.is_present("idempotent") || config.idempotent())
+                && !sub_m.is_present("no-idempotent");
+            if idempotent {
+                if let Err(Error(ErrorKind::AlreadyCurrent(ref mode, display_index), _)) = result {
+                    println!(
+                        "Mode {} is already current on display {}",
+                        mode, display_index
+                    );
+                    return Ok(());
+                }
             }
+            result
         }
         _ => Ok(()),
     }
 }
 
-quick_main!(run);
+fn main() {
+    if let Err(ref e) = run() {
+        let stderr = &mut io::stderr();
+        writeln!(stderr, "Error: {}", e).expect("Error writing to stderr");
+        for e in e.iter().skip(1) {
+            writeln!(stderr, "Caused by: {}", e).expect("Error writing to stderr");
+        }
+        std::process::exit(e.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding::PixelEncoding;
+    use layout::Relation;
+
+    fn test_mode(display: DisplayIndex, width: u64, height: u64, current: bool) -> Mode {
+        Mode {
+            display: display,
+            cgmode: None,
+            width: width,
+            height: height,
+            pixel_width: width * 2,
+            pixel_height: height * 2,
+            refresh_rate: RefreshRate::Unknown,
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0x3,
+            bit_depth: 32,
+            current: current,
+        }
+    }
+
+    fn test_screen_resolution() -> ScreenResolution {
+        ScreenResolution {
+            displays: vec![69733378, 188940995],
+            modes: vec![
+                test_mode(0, 1920, 1200, false),
+                test_mode(0, 1440, 900, true),
+                test_mode(1, 1920, 1200, true),
+            ],
+            history: None,
+            hooks: Hooks::default(),
+            force: false,
+            exact: false,
+            link: None,
+            origins: vec![Origin { x: 0, y: 0 }, Origin { x: 1440, y: 0 }],
+        }
+    }
+
+    #[test]
+    fn plan_current_mode_matching() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .plan_current_mode("1920x1200x32@0", 0, &mut vec)
+            .expect("Error while testing plan_current_mode");
+
+        assert_eq!(
+            "Would set display 0 (id 69733378) to 1920x1200x32@0 - pixel 3840x2400 - flags: 0x0000003\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn plan_current_mode_already_current() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .plan_current_mode("1920x1200x32@0", 1, &mut vec)
+            .expect("Error while testing plan_current_mode");
+
+        assert_eq!(
+            "Would set display 1 (id 188940995) to 1920x1200x32@0 - pixel 3840x2400 - flags: 0x0000003 (already current)\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn plan_current_mode_no_match() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .plan_current_mode("1440x900x32@0", 1, &mut vec)
+            .expect("Error while testing plan_current_mode");
+
+        assert_eq!(
+            "No mode matching 1440x900x32@0 on display 1 (id 188940995)\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn set_current_mode_no_display() {
+        let screen_resolution = test_screen_resolution();
+
+        let result = screen_resolution.set_current_mode("1920x1200x32@0", 2, None);
+
+        assert_eq!(
+            errors::EXIT_NO_SUCH_DISPLAY,
+            result.unwrap_err().exit_code()
+        );
+    }
+
+    #[test]
+    fn set_current_mode_no_match() {
+        let screen_resolution = test_screen_resolution();
+
+        let result = screen_resolution.set_current_mode("1440x900x32@0", 1, None);
+
+        assert_eq!(
+            errors::EXIT_NO_MATCHING_MODE,
+            result.unwrap_err().exit_code()
+        );
+    }
+
+    #[test]
+    fn set_current_mode_already_current() {
+        let screen_resolution = test_screen_resolution();
+
+        let result = screen_resolution.set_current_mode("1440x900x32@0", 0, None);
+
+        assert_eq!(
+            errors::EXIT_ALREADY_CURRENT,
+            result.unwrap_err().exit_code()
+        );
+    }
+
+    #[test]
+    fn plan_current_mode_close_refresh_rate() {
+        let mut screen_resolution = test_screen_resolution();
+        screen_resolution.modes[0].refresh_rate = RefreshRate::from_hz(59.94);
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .plan_current_mode("1920x1200@60", 0, &mut vec)
+            .expect("Error while testing plan_current_mode");
+
+        assert_eq!(
+            "Would set display 0 (id 69733378) to 1920x1200x32@59.94 - pixel 3840x2400 - flags: 0x0000003\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+
+        let screen_resolution = screen_resolution.with_exact(true);
+        let result = screen_resolution.set_current_mode("1920x1200@60", 0, None);
+
+        assert_eq!(
+            errors::EXIT_NO_MATCHING_MODE,
+            result.unwrap_err().exit_code()
+        );
+    }
+
+    #[test]
+    fn plan_current_mode_with_encoding() {
+        let mut screen_resolution = test_screen_resolution();
+        screen_resolution.modes[0].pixel_encoding =
+            PixelEncoding::from_io_encoding("--RRRRRRRRRRGGGGGGGGGGBBBBBBBBBB");
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .plan_current_mode("1920x1200@0:10bpc", 0, &mut vec)
+            .expect("Error while testing plan_current_mode");
+        screen_resolution
+            .plan_current_mode("1920x1200@0:8bpc", 0, &mut vec)
+            .expect("Error while testing plan_current_mode");
+
+        assert_eq!(
+            "Would set display 0 (id 69733378) to 1920x1200x32@0 - pixel 3840x2400 - flags: 0x0000003\n\
+             No mode matching 1920x1200@0:8bpc on display 0 (id 69733378)\n",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn set_vrr_without_range() {
+        let screen_resolution = test_screen_resolution();
+
+        assert_eq!(true, screen_resolution.set_vrr(0, false).is_ok());
+        assert_eq!(true, screen_resolution.set_vrr(0, true).is_err());
+        assert_eq!(
+            errors::EXIT_NO_SUCH_DISPLAY,
+            screen_resolution.set_vrr(2, false).unwrap_err().exit_code()
+        );
+    }
+
+    #[test]
+    fn plan_custom_mode() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .plan_custom_mode("2560x1080@75", 0, Standard::Cvt, &mut vec)
+            .expect("Error while testing plan_custom_mode");
+
+        let output = String::from_utf8(vec).unwrap();
+        assert_eq!(
+            "Would add mode 2560x1080@75 to display 0 with:",
+            output.lines().next().unwrap()
+        );
+        assert_eq!(
+            true,
+            output
+                .lines()
+                .last()
+                .unwrap()
+                .starts_with("Modeline \"2560x1080_75.00\"")
+        );
+    }
+
+    #[test]
+    fn plan_custom_mode_existing() {
+        let mut screen_resolution = test_screen_resolution();
+        screen_resolution.modes[1].refresh_rate = RefreshRate::from_hz(60.0);
+        let mut vec = Vec::<u8>::new();
+
+        assert_eq!(
+            true,
+            screen_resolution
+                .plan_custom_mode("1440x900@60", 0, Standard::Cvt, &mut vec)
+                .is_err()
+        );
+        assert_eq!(
+            true,
+            screen_resolution
+                .plan_custom_mode("1440x900@75", 0, Standard::Cvt, &mut vec)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn list_modes_long_with_link() {
+        let mut screen_resolution =
+            test_screen_resolution().with_link(Some(Link::new(link::LinkKind::Hdmi14)));
+        screen_resolution.modes[0].refresh_rate = RefreshRate::from_hz(60.0);
+        screen_resolution.modes[1].refresh_rate = RefreshRate::from_hz(60.0);
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .list_modes(true, &ModeFilter::new(), &mut vec)
+            .expect("Error while testing list_modes");
+
+        let output = String::from_utf8(vec).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(true, lines[0].ends_with(", HDMI 1.4: exceeds"));
+        assert_eq!(true, lines[1].ends_with(", HDMI 1.4: fits"));
+        assert_eq!(false, lines[2].contains("HDMI"));
+    }
+
+    #[test]
+    fn recommend_needs_size() {
+        let mut screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        assert_eq!(
+            true,
+            screen_resolution
+                .recommend(0, None, None, 600, &mut vec)
+                .is_err()
+        );
+        screen_resolution.modes[0].size_mm = Some((286, 179));
+        // Only HiDPI modes, none of them flagged native
+        assert_eq!(
+            true,
+            screen_resolution
+                .recommend(0, None, None, 500, &mut vec)
+                .is_err()
+        );
+        screen_resolution.modes[0].io_flags |= ModeFlags::NATIVE.bits();
+        screen_resolution
+            .recommend(0, None, None, 500, &mut vec)
+            .expect("Error while testing recommend");
+
+        assert_eq!(
+            "Display 0: 286x179 mm, native 3840x2400, seen from 50 cm",
+            String::from_utf8(vec).unwrap().lines().next().unwrap()
+        );
+        assert_eq!(
+            errors::EXIT_NO_SUCH_DISPLAY,
+            screen_resolution
+                .recommend(2, Some((286, 179)), None, 500, &mut Vec::new())
+                .unwrap_err()
+                .exit_code()
+        );
+    }
+
+    #[test]
+    fn common_modes_of_displays() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .common_modes(&[0, 1], &mut vec)
+            .expect("Error while testing common_modes");
+
+        assert_eq!(
+            "* 0: 1920x1200@0, 1: 1920x1200@0
+",
+            String::from_utf8(vec).unwrap()
+        );
+        assert_eq!(
+            true,
+            screen_resolution
+                .common_modes(&[0], &mut Vec::new())
+                .is_err()
+        );
+        assert_eq!(
+            errors::EXIT_NO_SUCH_DISPLAY,
+            screen_resolution
+                .common_modes(&[0, 2], &mut Vec::new())
+                .unwrap_err()
+                .exit_code()
+        );
+    }
+
+    #[test]
+    fn print_layout() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .print_layout(&mut vec)
+            .expect("Error while testing print_layout");
+
+        assert_eq!(
+            "Display 0: 1440x900 at 0,0\n\
+             Display 1: 1920x1200 at 1440,0\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn arrange_dry_run() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .arrange(
+                1,
+                Placement::Next(Relation::LeftOf, 0, Align::End),
+                true,
+                &mut vec,
+            ).expect("Error while testing arrange");
+        screen_resolution
+            .arrange(0, Placement::At(Origin { x: 0, y: -900 }), true, &mut vec)
+            .expect("Error while testing arrange");
+
+        assert_eq!(
+            "Would move display 1 to -1920,-300\n\
+             Would move display 0 to 0,-900\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn arrange_invalid() {
+        let screen_resolution = test_screen_resolution();
+        let next_to = |reference| Placement::Next(Relation::Above, reference, Align::Center);
+
+        assert_eq!(
+            true,
+            screen_resolution
+                .arrange(1, next_to(1), true, &mut Vec::new())
+                .is_err()
+        );
+        assert_eq!(
+            errors::EXIT_NO_SUCH_DISPLAY,
+            screen_resolution
+                .arrange(1, next_to(2), true, &mut Vec::new())
+                .unwrap_err()
+                .exit_code()
+        );
+    }
+
+    #[test]
+    fn mirror_enable_dry_run() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .mirror_enable(0, &[1], true, true, &mut vec)
+            .expect("Error while testing mirror_enable");
+
+        assert_eq!(
+            "Would set display 0 to 1920x1200x32@0\n\
+             Would set display 1 to 1920x1200x32@0\n\
+             Would mirror display 0 (id 69733378) on display 1 (id 188940995)\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn mirror_enable_invalid() {
+        let screen_resolution = test_screen_resolution();
+
+        assert_eq!(
+            true,
+            screen_resolution
+                .mirror_enable(0, &[0], false, true, &mut Vec::new())
+                .is_err()
+        );
+        assert_eq!(
+            errors::EXIT_NO_SUCH_DISPLAY,
+            screen_resolution
+                .mirror_enable(0, &[2], false, true, &mut Vec::new())
+                .unwrap_err()
+                .exit_code()
+        );
+    }
+
+    #[test]
+    fn mirror_disable_dry_run() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .mirror_disable(&[1], true, &mut vec)
+            .expect("Error while testing mirror_disable");
+
+        assert_eq!(
+            "Would stop mirroring on display id 188940995\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn set_current_mode_unsafe() {
+        let mut screen_resolution = test_screen_resolution();
+        screen_resolution.modes[0].io_flags = 0;
+
+        let result = screen_resolution.set_current_mode("1920x1200x32@0", 0, None);
+
+        assert_eq!(errors::EXIT_UNSAFE_MODE, result.unwrap_err().exit_code());
+    }
+
+    #[test]
+    fn clear_journal_keeps_configuration_error() {
+        // A directory cannot be removed as a journal file
+        let path = std::env::temp_dir();
+
+        let result = ScreenResolution::clear_journal(
+            &path,
+            Err(ErrorKind::ConfigurationRejected(errors::CGError::CannotComplete).into()),
+        );
+
+        assert_eq!(
+            errors::EXIT_CONFIGURATION_REJECTED,
+            result.unwrap_err().exit_code()
+        );
+        assert_eq!(
+            true,
+            ScreenResolution::clear_journal(&path, Ok(())).is_err()
+        );
+    }
+
+    #[test]
+    fn parse_wanted_mode_without_bit_depth() {
+        let mode = ScreenResolution::parse_wanted_mode("1920x1080@60", 1).unwrap();
+
+        assert_eq!("1920x1080x32@60", mode.for_select());
+    }
+
+    #[test]
+    fn parse_wanted_mode_with_name() {
+        let mode = ScreenResolution::parse_wanted_mode("qhd@144", 0).unwrap();
+
+        assert_eq!("2560x1440x32@144", mode.for_select());
+    }
+
+    #[test]
+    fn list_modes_by_name() {
+        let screen_resolution = test_screen_resolution();
+        let mut filter = ModeFilter::new();
+        filter.name = names::by_name("wuxga");
+        let mut vec = Vec::<u8>::new();
+
+        screen_resolution
+            .list_modes(false, &filter, &mut vec)
+            .expect("Error while testing list_modes");
+
+        let listed = String::from_utf8(vec).unwrap();
+        assert_eq!(2, listed.lines().count());
+        assert_eq!(true, listed.lines().all(|line| line.contains("1920x1200")));
+    }
+
+    #[test]
+    fn find_display_by_index() {
+        let screen_resolution = test_screen_resolution();
+
+        assert_eq!(1, screen_resolution.find_display("1").unwrap());
+    }
+
+    #[test]
+    fn verify_applied_after_retry() {
+        let wanted_mode = test_mode(0, 1920, 1200, false);
+        let verification = Verification {
+            retries: 2,
+            delay: Duration::from_millis(0),
+        };
+        let mut observed = vec![
+            test_mode(0, 1920, 1200, true),
+            test_mode(0, 1440, 900, true),
+        ];
+
+        let result = ScreenResolution::verify_applied(&wanted_mode, &verification, || {
+            Ok(observed.pop().unwrap())
+        });
+
+        assert_eq!(true, result.is_ok());
+        assert_eq!(true, observed.is_empty());
+    }
+
+    #[test]
+    fn verify_applied_mismatch() {
+        let wanted_mode = test_mode(0, 1920, 1200, false);
+        let verification = Verification {
+            retries: 2,
+            delay: Duration::from_millis(0),
+        };
+        let mut attempts = 0;
+
+        let result = ScreenResolution::verify_applied(&wanted_mode, &verification, || {
+            attempts += 1;
+            Ok(test_mode(0, 1440, 900, true))
+        });
+
+        assert_eq!(3, attempts);
+        assert_eq!(
+            "Display 0 is at 1440x900x32@0 instead of 1920x1200x32@0",
+            format!("{}", result.unwrap_err())
+        );
+    }
+
+    #[test]
+    fn plan_current_mode_no_display() {
+        let screen_resolution = test_screen_resolution();
+        let mut vec = Vec::<u8>::new();
+
+        let result = screen_resolution.plan_current_mode("1920x1200x32@0", 2, &mut vec);
+
+        assert_eq!(true, result.is_err());
+    }
+}
@@ -0,0 +1,358 @@
+use core_graphics::base;
+use core_graphics::display::{CGDirectDisplayID, CGDisplay, CGDisplayConfigRef, CGDisplayMode};
+use std::io;
+
+use configuration;
+use errors::*;
+use mode::Mode;
+use refresh::RefreshRate;
+
+/// Aspect ratios closer than this are taken as the same: the image is scaled without bars.
+const ASPECT_TOLERANCE: f64 = 0.02;
+/// Letterboxed alternatives proposed when no mode is shared.
+const MAX_ALTERNATIVES: usize = 3;
+
+/// A mode per display, in the order the displays were given.
+pub struct CommonMode<'a> {
+    pub modes: Vec<&'a Mode>,
+    /// Part of the screen of each display that the mirrored image covers, from 0 to 1.
+    pub coverage: Vec<f64>,
+}
+
+impl<'a> CommonMode<'a> {
+    /// Whether all displays show the same resolution.
+    pub fn is_shared(&self) -> bool {
+        self.modes
+            .iter()
+            .all(|mode| (mode.width, mode.height) == (self.modes[0].width, self.modes[0].height))
+    }
+
+    fn min_coverage(&self) -> f64 {
+        self.coverage.iter().cloned().fold(1.0, f64::min)
+    }
+}
+
+/// Refresh rates are compatible when they match within the usual tolerance. Built-in panels
+/// report no rate and go with any.
+fn compatible_refresh(a: &RefreshRate, b: &RefreshRate) -> bool {
+    *a == RefreshRate::Unknown || *b == RefreshRate::Unknown || a.matches(b, false)
+}
+
+/// Part of a screen of that resolution covered by an image of the source resolution scaled to
+/// fit it.
+fn coverage(source: &Mode, screen: &Mode) -> f64 {
+    let source_aspect = source.width as f64 / source.height as f64;
+    let screen_aspect = screen.width as f64 / screen.height as f64;
+    let ratio = source_aspect.min(screen_aspect) / source_aspect.max(screen_aspect);
+    if 1.0 - ratio <= ASPECT_TOLERANCE {
+        1.0
+    } else {
+        ratio
+    }
+}
+
+/// The modes shared by all displays, best first: larger resolutions, then higher refresh rates.
+/// Each display gives the list of its modes.
+pub fn shared_modes<'a>(displays: &[Vec<&'a Mode>]) -> Vec<CommonMode<'a>> {
+    let mut shared: Vec<CommonMode> = Vec::new();
+    let (first, others) = match displays.split_first() {
+        Some(split) => split,
+        None => return shared,
+    };
+    for &mode in first {
+        let matching: Option<Vec<&Mode>> = others
+            .iter()
+            .map(|modes| {
+                modes
+                    .iter()
+                    .filter(|other| {
+                        (other.width, other.height) == (mode.width, mode.height)
+                            && compatible_refresh(&other.refresh_rate, &mode.refresh_rate)
+                    }).max_by_key(|other| other.refresh_rate)
+                    .cloned()
+            }).collect();
+        if let Some(mut matching) = matching {
+            // Only the highest rate is kept for a resolution
+            if shared.iter().any(|common| {
+                (common.modes[0].width, common.modes[0].height) == (mode.width, mode.height)
+                    && common.modes[0].refresh_rate >= mode.refresh_rate
+            }) {
+                continue;
+            }
+            shared.retain(|common| {
+                (common.modes[0].width, common.modes[0].height) != (mode.width, mode.height)
+            });
+            matching.insert(0, mode);
+            shared.push(CommonMode {
+                coverage: vec![1.0; matching.len()],
+                modes: matching,
+            });
+        }
+    }
+    shared.sort_by(|a, b| {
+        let area = |common: &CommonMode| common.modes[0].width * common.modes[0].height;
+        area(b)
+            .cmp(&area(a))
+            .then(b.modes[0].refresh_rate.cmp(&a.modes[0].refresh_rate))
+    });
+    shared
+}
+
+/// Mirroring with a different resolution on each display, for when none is shared. The first
+/// display gives the image, which the others scale to fit their screen, with bars when the aspect
+/// ratios differ. Alternatives that cover the most of every screen come first, then the ones
+/// with larger images.
+pub fn letterbox_alternatives<'a>(displays: &[Vec<&'a Mode>]) -> Vec<CommonMode<'a>> {
+    let mut alternatives: Vec<CommonMode> = Vec::new();
+    let (first, others) = match displays.split_first() {
+        Some(split) => split,
+        None => return alternatives,
+    };
+    for &source in first {
+        let best: Option<Vec<&Mode>> = others
+            .iter()
+            .map(|modes| {
+                modes
+                    .iter()
+                    .max_by(|a, b| {
+                        coverage(source, a)
+                            .partial_cmp(&coverage(source, b))
+                            .unwrap()
+                            .then((a.width * a.height).cmp(&(b.width * b.height)))
+                            .then(a.refresh_rate.cmp(&b.refresh_rate))
+                    }).cloned()
+            }).collect();
+        if let Some(mut modes) = best {
+            modes.insert(0, source);
+            let coverage = modes
+                .iter()
+                .map(|screen| coverage(source, screen))
+                .collect();
+            alternatives.push(CommonMode { modes, coverage });
+        }
+    }
+    alternatives.sort_by(|a, b| {
+        b.min_coverage()
+            .partial_cmp(&a.min_coverage())
+            .unwrap()
+            .then(
+                (b.modes[0].width * b.modes[0].height).cmp(&(a.modes[0].width * a.modes[0].height)),
+            )
+    });
+    alternatives.dedup_by(|a, b| {
+        a.modes
+            .iter()
+            .zip(b.modes.iter())
+            .all(|(a, b)| (a.width, a.height) == (b.width, b.height))
+    });
+    alternatives.truncate(MAX_ALTERNATIVES);
+    alternatives
+}
+
+fn print_common_mode(common: &CommonMode, output: &mut io::Write) -> Result<()> {
+    let modes: Vec<String> = common
+        .modes
+        .iter()
+        .map(|mode| {
+            format!(
+                "{}: {}x{}@{}",
+                mode.display, mode.width, mode.height, mode.refresh_rate
+            )
+        }).collect();
+    write!(output, "{}", modes.join(", "))?;
+    for (mode, &coverage) in common.modes.iter().zip(common.coverage.iter()) {
+        if coverage < 1.0 {
+            write!(
+                output,
+                " - covers {:.0}% of display {}",
+                coverage * 100.0,
+                mode.display
+            )?;
+        }
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+/// Print the modes shared by the displays, best first, or letterboxed alternatives when they
+/// share none.
+pub fn print_common_modes(displays: &[Vec<&Mode>], output: &mut io::Write) -> Result<()> {
+    let shared = shared_modes(displays);
+    if shared.is_empty() {
+        writeln!(output, "No shared mode, letterboxed alternatives:")?;
+        for alternative in letterbox_alternatives(displays) {
+            write!(output, "  ")?;
+            // The resolution can be shared without a refresh rate that all displays take
+            if alternative.is_shared() {
+                write!(output, "(different refresh rates) ")?;
+            }
+            print_common_mode(&alternative, output)?;
+        }
+    } else {
+        for (i, common) in shared.iter().enumerate() {
+            write!(output, "{} ", if i == 0 { "*" } else { " " })?;
+            print_common_mode(common, output)?;
+        }
+    }
+    Ok(())
+}
+
+/// The display id that stands for no display, to stop mirroring.
+#[allow(non_upper_case_globals)]
+const kCGNullDirectDisplay: CGDirectDisplayID = 0;
+
+extern "C" {
+    fn CGConfigureDisplayMirrorOfDisplay(
+        config: CGDisplayConfigRef,
+        display: CGDirectDisplayID,
+        master: CGDirectDisplayID,
+    ) -> base::CGError;
+}
+
+/// Make the target displays mirror the source display, or stop mirroring when there is no source,
+/// in a single display configuration that first sets the given modes.
+pub fn configure_mirroring(
+    targets: &[CGDirectDisplayID],
+    source: Option<CGDirectDisplayID>,
+    modes: &[(CGDirectDisplayID, &CGDisplayMode)],
+) -> Result<()> {
+    let display = CGDisplay::new(source.unwrap_or(targets[0]));
+    configuration::with_configuration(&display, |config_ref| {
+        for &(display_id, cgmode) in modes {
+            CGDisplay::new(display_id)
+                .configure_display_with_display_mode(&config_ref, cgmode)
+                .map_err(|e| Error::from(ErrorKind::ConfigurationRejected(e.into())))?;
+        }
+        for &target in targets {
+            configuration::configured(unsafe {
+                CGConfigureDisplayMirrorOfDisplay(
+                    config_ref,
+                    target,
+                    source.unwrap_or(kCGNullDirectDisplay),
+                )
+            })?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_mode(display: u8, width: u64, height: u64, hz: f64) -> Mode {
+        Mode {
+            display,
+            cgmode: None,
+            width,
+            height,
+            pixel_width: width,
+            pixel_height: height,
+            refresh_rate: RefreshRate::from_hz(hz),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0x3,
+            bit_depth: 32,
+            current: false,
+        }
+    }
+
+    #[test]
+    fn shared_modes_with_refresh_tolerance() {
+        let laptop = vec![
+            test_mode(0, 1920, 1080, 0.0),
+            test_mode(0, 1440, 900, 0.0),
+            test_mode(0, 1280, 720, 0.0),
+        ];
+        let projector = vec![
+            test_mode(1, 1920, 1080, 59.94),
+            test_mode(1, 1920, 1080, 50.0),
+            test_mode(1, 1280, 720, 60.0),
+            test_mode(1, 1024, 768, 60.0),
+        ];
+        let modes = vec![
+            laptop.iter().collect::<Vec<&Mode>>(),
+            projector.iter().collect::<Vec<&Mode>>(),
+        ];
+
+        let shared = shared_modes(&modes);
+
+        assert_eq!(2, shared.len());
+        assert_eq!(true, shared[0].is_shared());
+        assert_eq!(1920, shared[0].modes[1].width);
+        assert_eq!(RefreshRate::from_hz(59.94), shared[0].modes[1].refresh_rate);
+        assert_eq!(1280, shared[1].modes[0].width);
+    }
+
+    #[test]
+    fn letterbox_when_nothing_is_shared() {
+        let laptop = vec![test_mode(0, 1440, 900, 60.0), test_mode(0, 1280, 800, 60.0)];
+        let projector = vec![test_mode(1, 1024, 768, 60.0), test_mode(1, 1280, 720, 60.0)];
+        let modes = vec![
+            laptop.iter().collect::<Vec<&Mode>>(),
+            projector.iter().collect::<Vec<&Mode>>(),
+        ];
+
+        assert_eq!(0, shared_modes(&modes).len());
+        let alternatives = letterbox_alternatives(&modes);
+
+        // 16:10 fits better on 16:9 than on 4:3
+        assert_eq!(1440, alternatives[0].modes[0].width);
+        assert_eq!(1280, alternatives[0].modes[1].width);
+        assert_eq!(true, (alternatives[0].coverage[1] - 0.9).abs() < 0.01);
+        assert_eq!(false, alternatives[0].is_shared());
+    }
+
+    #[test]
+    fn letterbox_incompatible_refresh() {
+        let monitor = vec![test_mode(0, 1920, 1080, 144.0)];
+        let projector = vec![test_mode(1, 1920, 1080, 60.0)];
+        let modes = vec![
+            monitor.iter().collect::<Vec<&Mode>>(),
+            projector.iter().collect::<Vec<&Mode>>(),
+        ];
+        let mut vec = Vec::<u8>::new();
+
+        print_common_modes(&modes, &mut vec).expect("Error while testing print_common_modes");
+
+        assert_eq!(
+            "No shared mode, letterboxed alternatives:\n  \
+             (different refresh rates) 0: 1920x1080@144, 1: 1920x1080@60\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn aspect_tolerance() {
+        let wide = test_mode(0, 1920, 1080, 60.0);
+        let almost_wide = test_mode(1, 1366, 768, 60.0);
+        let square = test_mode(1, 1024, 768, 60.0);
+
+        assert_eq!(1.0, coverage(&wide, &almost_wide));
+        assert_eq!(true, coverage(&wide, &square) < 0.8);
+    }
+
+    #[test]
+    fn print_shared_and_letterboxed() {
+        let laptop = vec![test_mode(0, 1440, 900, 60.0)];
+        let projector = vec![test_mode(1, 1280, 720, 60.0)];
+        let mut modes = vec![
+            laptop.iter().collect::<Vec<&Mode>>(),
+            projector.iter().collect::<Vec<&Mode>>(),
+        ];
+        let mut vec = Vec::<u8>::new();
+
+        print_common_modes(&modes, &mut vec).expect("Error while testing print_common_modes");
+        modes[1] = laptop.iter().collect();
+        print_common_modes(&modes, &mut vec).expect("Error while testing print_common_modes");
+
+        assert_eq!(
+            "No shared mode, letterboxed alternatives:\n  \
+             0: 1440x900@60, 1: 1280x720@60 - covers 90% of display 1\n\
+             * 0: 1440x900@60, 0: 1440x900@60\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+}
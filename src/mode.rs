@@ -2,7 +2,11 @@ use core_graphics::display::CGDisplayMode;
 
 use std::io;
 
+use encoding::{EncodingCapabilities, EncodingFilter, PixelEncoding};
 use errors::*;
+use flags::{self, ModeFlags};
+use names::{self, ResolutionName};
+use refresh::RefreshRate;
 
 pub type DisplayIndex = u8;
 
@@ -19,7 +23,15 @@ pub struct Mode {
     pub height: u64,
     pub pixel_width: u64,
     pub pixel_height: u64,
-    pub refresh_rate: f64,
+    pub refresh_rate: RefreshRate,
+    /// Range of refresh rates the display can follow, as a `RefreshRate::Variable`, when it
+    /// supports adaptive sync.
+    pub vrr_range: Option<RefreshRate>,
+    /// Width and height of the image on the display in millimeters, when known.
+    pub size_mm: Option<(u32, u32)>,
+    pub pixel_encoding: Option<PixelEncoding>,
+    /// Encodings the display takes, from its EDID, when it has one.
+    pub encoding_capabilities: Option<EncodingCapabilities>,
     pub io_flags: u32,
     pub bit_depth: usize,
     pub current: bool,
@@ -33,7 +45,11 @@ impl Mode {
             height: cgmode.height(),
             pixel_width: cgmode.pixel_width(),
             pixel_height: cgmode.pixel_height(),
-            refresh_rate: cgmode.refresh_rate(),
+            refresh_rate: RefreshRate::from_hz(cgmode.refresh_rate()),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: PixelEncoding::from_io_encoding(&cgmode.pixel_encoding().to_string()),
+            encoding_capabilities: None,
             io_flags: cgmode.io_flags(),
             bit_depth: cgmode.bit_depth(),
             cgmode: Some(cgmode),
@@ -84,6 +100,15 @@ impl Mode {
             hidpi,
             screen_format
         ).chain_err(|| "Could not print long")?;
+        if let Some(pixel_encoding) = self.pixel_encoding {
+            write!(output, " - {}", pixel_encoding).chain_err(|| "Could not print short")?;
+        }
+        if let Some(vrr_range) = self.vrr_range {
+            write!(output, " - VRR {}", vrr_range).chain_err(|| "Could not print short")?;
+        }
+        if !self.is_safe() {
+            write!(output, " - unsafe").chain_err(|| "Could not print short")?;
+        }
         Ok(())
     }
 
@@ -96,20 +121,82 @@ impl Mode {
         };
         write!(
             output,
-            "{}Display {}: {}x{}, refresh rate: {}, bitDepth: {}, flags: 0x{:07X}, {}, {}",
+            "{}Display {}: {}x{}, refresh rate: {}, bitDepth: {}, flags: {}, {}, {}",
             if self.current { "*" } else { " " },
             self.display,
             self.width,
             self.height,
             self.refresh_rate,
             self.bit_depth,
-            self.io_flags,
+            flags::describe(self.io_flags),
             hidpi,
             screen_format
         ).chain_err(|| "Could not print long")?;
+        if let Some(resolution_name) = self.name() {
+            write!(output, ", {}", resolution_name).chain_err(|| "Could not print long")?;
+        }
+        if let Some(pixel_encoding) = self.pixel_encoding {
+            write!(output, ", {}", pixel_encoding).chain_err(|| "Could not print long")?;
+        }
+        if let Some(ref capabilities) = self.encoding_capabilities {
+            write!(output, ", display takes {}", capabilities)
+                .chain_err(|| "Could not print long")?;
+        }
+        if let Some(vrr_range) = self.vrr_range {
+            write!(output, ", VRR {} Hz", vrr_range).chain_err(|| "Could not print long")?;
+        }
+        if let Some((backing, logical)) = self.ppi() {
+            write!(
+                output,
+                ", PPI {:.0} backing, {:.0} logical",
+                backing, logical
+            ).chain_err(|| "Could not print long")?;
+        }
+        if !self.is_safe() {
+            write!(output, ", unsafe").chain_err(|| "Could not print long")?;
+        }
         Ok(())
     }
 
+    /// Whether this mode will do for the wanted one, see `RefreshRate::matches`.
+    pub fn matches(&self, wanted: &Mode, exact: bool) -> bool {
+        self.display == wanted.display
+            && self.width == wanted.width
+            && self.height == wanted.height
+            && self.bit_depth == wanted.bit_depth
+            && self.refresh_rate.matches(&wanted.refresh_rate, exact)
+    }
+
+    /// Pixels per inch of the backing and of the logical resolution, across the width of the
+    /// display. They are the same unless the mode is HiDPI.
+    pub fn ppi(&self) -> Option<(f64, f64)> {
+        match self.size_mm {
+            Some((width_mm, _)) if width_mm > 0 => {
+                let width_inches = f64::from(width_mm) / 25.4;
+                Some((
+                    self.pixel_width as f64 / width_inches,
+                    self.width as f64 / width_inches,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn flags(&self) -> ModeFlags {
+        ModeFlags::from_bits_truncate(self.io_flags)
+    }
+
+    /// Whether the OS considers the mode usable on the display. Other modes are hidden unless
+    /// asked for, and only set when forced.
+    pub fn is_safe(&self) -> bool {
+        self.flags().intersects(ModeFlags::VALID | ModeFlags::SAFE)
+    }
+
+    /// The marketing name of the resolution, like `QHD`, if it has one.
+    pub fn name(&self) -> Option<&'static str> {
+        names::by_resolution(self.width, self.height).map(|resolution_name| resolution_name.name)
+    }
+
     pub fn print_mode(&self, long: bool, output: &mut io::Write) -> Result<()> {
         match long {
             false => self.print_short(output)?,
@@ -136,9 +223,48 @@ impl PartialEq for Mode {
     }
 }
 
+/// Which modes to list.
+pub struct ModeFilter {
+    /// Only modes with the resolution of that name.
+    pub name: Option<&'static ResolutionName>,
+    /// Only modes with all of these flags.
+    pub with_flags: ModeFlags,
+    /// Only modes with none of these flags.
+    pub without_flags: ModeFlags,
+    /// Only modes with a matching pixel encoding.
+    pub encoding: Option<EncodingFilter>,
+}
+
+impl ModeFilter {
+    /// A filter letting every mode through.
+    pub fn new() -> ModeFilter {
+        ModeFilter {
+            name: None,
+            with_flags: ModeFlags::empty(),
+            without_flags: ModeFlags::empty(),
+            encoding: None,
+        }
+    }
+
+    pub fn matches(&self, mode: &Mode) -> bool {
+        self.name.map_or(true, |name| {
+            mode.width == name.width && mode.height == name.height
+        }) && mode.flags().contains(self.with_flags)
+            && !mode.flags().intersects(self.without_flags)
+            && self.encoding.as_ref().map_or(true, |encoding| {
+                encoding.matches(mode.pixel_encoding.as_ref())
+                    || mode
+                        .encoding_capabilities
+                        .as_ref()
+                        .map_or(false, |capabilities| capabilities.allows(encoding))
+            })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use encoding::ColorFormat;
 
     #[test]
     fn partial_eq_equals() {
@@ -149,7 +275,11 @@ mod tests {
             height: 600,
             pixel_width: 0,
             pixel_height: 0,
-            refresh_rate: 75.0,
+            refresh_rate: RefreshRate::from_hz(75.0),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 0,
             bit_depth: 32,
             current: true,
@@ -161,7 +291,11 @@ mod tests {
             height: 600,
             pixel_width: 0,
             pixel_height: 0,
-            refresh_rate: 75.0,
+            refresh_rate: RefreshRate::from_hz(75.0),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 0,
             bit_depth: 32,
             current: false,
@@ -178,7 +312,11 @@ mod tests {
             height: 600,
             pixel_width: 0,
             pixel_height: 0,
-            refresh_rate: 0.0,
+            refresh_rate: RefreshRate::Unknown,
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 0,
             bit_depth: 0,
             current: false,
@@ -190,7 +328,11 @@ mod tests {
             height: 640,
             pixel_width: 0,
             pixel_height: 0,
-            refresh_rate: 0.0,
+            refresh_rate: RefreshRate::Unknown,
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 0,
             bit_depth: 0,
             current: true,
@@ -207,7 +349,11 @@ mod tests {
             height: 600,
             pixel_width: 1024,
             pixel_height: 768,
-            refresh_rate: 21.2,
+            refresh_rate: RefreshRate::from_hz(21.2),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 123,
             bit_depth: 32,
             current: false,
@@ -233,7 +379,11 @@ mod tests {
             height: 600,
             pixel_width: 1024,
             pixel_height: 768,
-            refresh_rate: 21.2,
+            refresh_rate: RefreshRate::from_hz(21.2),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 123,
             bit_depth: 32,
             current: true,
@@ -259,7 +409,11 @@ mod tests {
             height: 600,
             pixel_width: 1024,
             pixel_height: 768,
-            refresh_rate: 21.2,
+            refresh_rate: RefreshRate::from_hz(21.2),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 123,
             bit_depth: 32,
             current: false,
@@ -271,7 +425,7 @@ mod tests {
             .expect("Error while testing print_short");
 
         assert_eq!(
-            " Display 1: 800x600, refresh rate: 21.2, bitDepth: 32, flags: 0x000007B, HiDPI, 4:3",
+            " Display 1: 800x600, refresh rate: 21.2, bitDepth: 32, flags: valid safe always-show not-resize requires-pan interlaced, HiDPI, 4:3",
             String::from_utf8(vec).unwrap().as_str()
         );
     }
@@ -285,7 +439,11 @@ mod tests {
             height: 600,
             pixel_width: 1024,
             pixel_height: 768,
-            refresh_rate: 21.2,
+            refresh_rate: RefreshRate::from_hz(21.2),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 123,
             bit_depth: 32,
             current: true,
@@ -297,11 +455,81 @@ mod tests {
             .expect("Error while testing print_short");
 
         assert_eq!(
-            "*Display 1: 800x600, refresh rate: 21.2, bitDepth: 32, flags: 0x000007B, HiDPI, 4:3",
+            "*Display 1: 800x600, refresh rate: 21.2, bitDepth: 32, flags: valid safe always-show not-resize requires-pan interlaced, HiDPI, 4:3",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn print_mode_long_named() {
+        let mode1 = Mode {
+            display: 0,
+            cgmode: None,
+            width: 2560,
+            height: 1440,
+            pixel_width: 2560,
+            pixel_height: 1440,
+            refresh_rate: RefreshRate::from_hz(144.0),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0x2000003,
+            bit_depth: 32,
+            current: false,
+        };
+        let mut vec = Vec::<u8>::new();
+
+        mode1
+            .print_mode(true, &mut vec)
+            .expect("Error while testing print_long");
+
+        assert_eq!(
+            " Display 0: 2560x1440, refresh rate: 144, bitDepth: 32, flags: valid safe native, , 16:9, QHD",
             String::from_utf8(vec).unwrap().as_str()
         );
     }
 
+    #[test]
+    fn matches_ntsc_refresh_rate() {
+        let wanted = Mode {
+            display: 0,
+            cgmode: None,
+            width: 1920,
+            height: 1080,
+            pixel_width: 0,
+            pixel_height: 0,
+            refresh_rate: RefreshRate::from_hz(60.0),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0,
+            bit_depth: 32,
+            current: false,
+        };
+        let mode = Mode {
+            display: 0,
+            cgmode: None,
+            width: 1920,
+            height: 1080,
+            pixel_width: 1920,
+            pixel_height: 1080,
+            refresh_rate: RefreshRate::from_hz(59.94),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0x2000003,
+            bit_depth: 32,
+            current: false,
+        };
+
+        assert_eq!(false, mode == wanted);
+        assert_eq!(true, mode.matches(&wanted, false));
+        assert_eq!(false, mode.matches(&wanted, true));
+    }
+
     #[test]
     fn mode_for_select() {
         let mode = Mode {
@@ -311,7 +539,11 @@ mod tests {
             height: 600,
             pixel_width: 1024,
             pixel_height: 768,
-            refresh_rate: 21.2,
+            refresh_rate: RefreshRate::from_hz(21.2),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
             io_flags: 123,
             bit_depth: 32,
             current: false,
@@ -319,4 +551,188 @@ mod tests {
         let actual = mode.for_select();
         assert_eq!("800x600x32@21.2", actual);
     }
+
+    #[test]
+    fn mode_filter_flags() {
+        let mode = Mode {
+            display: 0,
+            cgmode: None,
+            width: 2560,
+            height: 1440,
+            pixel_width: 2560,
+            pixel_height: 1440,
+            refresh_rate: RefreshRate::from_hz(60.0),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0x2000003,
+            bit_depth: 32,
+            current: false,
+        };
+        let mut filter = ModeFilter::new();
+        assert_eq!(true, filter.matches(&mode));
+
+        filter.with_flags = ModeFlags::NATIVE;
+        filter.without_flags = ModeFlags::INTERLACED;
+        assert_eq!(true, filter.matches(&mode));
+
+        filter.without_flags = ModeFlags::SAFE;
+        assert_eq!(false, filter.matches(&mode));
+
+        filter = ModeFilter::new();
+        filter.name = names::by_name("4k");
+        assert_eq!(false, filter.matches(&mode));
+    }
+
+    #[test]
+    fn mode_filter_encoding_capabilities() {
+        let mut mode = Mode {
+            display: 0,
+            cgmode: None,
+            width: 2560,
+            height: 1440,
+            pixel_width: 2560,
+            pixel_height: 1440,
+            refresh_rate: RefreshRate::from_hz(60.0),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: Some(PixelEncoding {
+                bits_per_component: 8,
+                format: ColorFormat::Rgb,
+                hdr: false,
+            }),
+            encoding_capabilities: None,
+            io_flags: 0x2000003,
+            bit_depth: 32,
+            current: false,
+        };
+        let mut filter = ModeFilter::new();
+        filter.encoding = Some("12bpc:ycbcr422".parse().unwrap());
+        assert_eq!(false, filter.matches(&mode));
+
+        mode.encoding_capabilities = Some(EncodingCapabilities {
+            max_bits_per_component: 12,
+            formats: vec![ColorFormat::Rgb, ColorFormat::YCbCr422],
+            hdr: false,
+        });
+        assert_eq!(true, filter.matches(&mode));
+        filter.encoding = Some("ycbcr420".parse().unwrap());
+        assert_eq!(false, filter.matches(&mode));
+    }
+
+    #[test]
+    fn print_mode_short_unsafe() {
+        let mode1 = Mode {
+            display: 0,
+            cgmode: None,
+            width: 800,
+            height: 600,
+            pixel_width: 800,
+            pixel_height: 600,
+            refresh_rate: RefreshRate::from_hz(60.0),
+            vrr_range: None,
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0,
+            bit_depth: 32,
+            current: false,
+        };
+        let mut vec = Vec::<u8>::new();
+
+        mode1
+            .print_mode(false, &mut vec)
+            .expect("Error while testing print_short");
+
+        assert_eq!(
+            " Display 0: 800x600x32@60   - pixel 800x600x32@60   -        - 4:3    - unsafe",
+            String::from_utf8(vec).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn print_mode_ppi() {
+        let mode1 = Mode {
+            display: 0,
+            cgmode: None,
+            width: 1440,
+            height: 900,
+            pixel_width: 2880,
+            pixel_height: 1800,
+            refresh_rate: RefreshRate::Unknown,
+            vrr_range: None,
+            size_mm: Some((332, 207)),
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0x2000003,
+            bit_depth: 32,
+            current: false,
+        };
+        let mut long = Vec::<u8>::new();
+
+        mode1
+            .print_mode(true, &mut long)
+            .expect("Error while testing print_long");
+
+        assert_eq!(
+            true,
+            String::from_utf8(long)
+                .unwrap()
+                .ends_with(", PPI 220 backing, 110 logical")
+        );
+        assert_eq!(
+            None,
+            Mode {
+                size_mm: Some((0, 0)),
+                cgmode: None,
+                ..mode1
+            }.ppi()
+        );
+    }
+
+    #[test]
+    fn print_mode_vrr_range() {
+        let mode1 = Mode {
+            display: 0,
+            cgmode: None,
+            width: 2560,
+            height: 1440,
+            pixel_width: 2560,
+            pixel_height: 1440,
+            refresh_rate: RefreshRate::from_hz(144.0),
+            vrr_range: Some(RefreshRate::Variable {
+                min: 48000,
+                max: 144000,
+            }),
+            size_mm: None,
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0x2000003,
+            bit_depth: 32,
+            current: false,
+        };
+        let mut long = Vec::<u8>::new();
+        let mut short = Vec::<u8>::new();
+
+        mode1
+            .print_mode(true, &mut long)
+            .expect("Error while testing print_long");
+        mode1
+            .print_mode(false, &mut short)
+            .expect("Error while testing print_short");
+
+        assert_eq!(
+            true,
+            String::from_utf8(long)
+                .unwrap()
+                .ends_with(", QHD, VRR 48-144 Hz")
+        );
+        assert_eq!(
+            true,
+            String::from_utf8(short)
+                .unwrap()
+                .ends_with(" - 16:9   - VRR 48-144")
+        );
+    }
 }
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+/// A common marketing name for a resolution.
+#[derive(Debug, PartialEq)]
+pub struct ResolutionName {
+    pub name: &'static str,
+    /// Other names accepted as input.
+    pub aliases: &'static [&'static str],
+    pub width: u64,
+    pub height: u64,
+}
+
+pub const NAMES: &[ResolutionName] = &[
+    ResolutionName {
+        name: "VGA",
+        aliases: &[],
+        width: 640,
+        height: 480,
+    },
+    ResolutionName {
+        name: "XGA",
+        aliases: &[],
+        width: 1024,
+        height: 768,
+    },
+    ResolutionName {
+        name: "HD",
+        aliases: &["720p"],
+        width: 1280,
+        height: 720,
+    },
+    ResolutionName {
+        name: "WXGA",
+        aliases: &[],
+        width: 1280,
+        height: 800,
+    },
+    ResolutionName {
+        name: "FHD",
+        aliases: &["1080p", "Full HD"],
+        width: 1920,
+        height: 1080,
+    },
+    ResolutionName {
+        name: "WUXGA",
+        aliases: &[],
+        width: 1920,
+        height: 1200,
+    },
+    ResolutionName {
+        name: "QHD",
+        aliases: &["1440p", "WQHD"],
+        width: 2560,
+        height: 1440,
+    },
+    ResolutionName {
+        name: "WQXGA",
+        aliases: &[],
+        width: 2560,
+        height: 1600,
+    },
+    ResolutionName {
+        name: "4K UHD",
+        aliases: &["4K", "UHD", "2160p"],
+        width: 3840,
+        height: 2160,
+    },
+    ResolutionName {
+        name: "5K",
+        aliases: &[],
+        width: 5120,
+        height: 2880,
+    },
+    ResolutionName {
+        name: "8K",
+        aliases: &["8K UHD", "4320p"],
+        width: 7680,
+        height: 4320,
+    },
+];
+
+/// Names are matched ignoring case, spaces and dashes: `4k-uhd` is `4K UHD`.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Reverse index of the table, from every normalized name and alias to its resolution.
+pub fn name_index() -> HashMap<String, &'static ResolutionName> {
+    let mut index = HashMap::new();
+    for resolution_name in NAMES.iter() {
+        index.insert(normalize(resolution_name.name), resolution_name);
+        for alias in resolution_name.aliases.iter() {
+            index.insert(normalize(alias), resolution_name);
+        }
+    }
+    index
+}
+
+/// The resolution known under the name or one of its aliases.
+pub fn by_name(name: &str) -> Option<&'static ResolutionName> {
+    name_index()
+        .get(&normalize(name))
+        .map(|&resolution_name| resolution_name)
+}
+
+/// The name of the resolution, if it has one.
+pub fn by_resolution(width: u64, height: u64) -> Option<&'static ResolutionName> {
+    NAMES
+        .iter()
+        .find(|resolution_name| resolution_name.width == width && resolution_name.height == height)
+}
+
+/// Replace a name in front of a mode with its resolution: `qhd@144` becomes `2560x1440@144` and
+/// `4kx30@60` becomes `3840x2160x30@60`. Modes that do not start with a known name give `None`.
+pub fn expand_mode(mode: &str) -> Option<String> {
+    let (head, refresh_rate) = match mode.find('@') {
+        Some(position) => mode.split_at(position),
+        None => (mode, ""),
+    };
+    let (name, bit_depth) = match head.rfind(|c| c == 'x' || c == 'X') {
+        Some(position)
+            if by_name(head).is_none()
+                && position + 1 < head.len()
+                && head[position + 1..].chars().all(|c| c.is_digit(10)) =>
+        {
+            head.split_at(position)
+        }
+        _ => (head, ""),
+    };
+    by_name(name).map(|resolution_name| {
+        format!(
+            "{}x{}{}{}",
+            resolution_name.width, resolution_name.height, bit_depth, refresh_rate
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn by_name_ignores_case_and_spaces() {
+        assert_eq!(Some("QHD"), by_name("qhd").map(|n| n.name));
+        assert_eq!(Some("4K UHD"), by_name("4k").map(|n| n.name));
+        assert_eq!(Some("4K UHD"), by_name("4k-uhd").map(|n| n.name));
+        assert_eq!(Some("FHD"), by_name("Full HD").map(|n| n.name));
+        assert_eq!(None, by_name("svga"));
+    }
+
+    #[test]
+    fn by_resolution_finds_name() {
+        assert_eq!(Some("WUXGA"), by_resolution(1920, 1200).map(|n| n.name));
+        assert_eq!(None, by_resolution(1440, 900));
+    }
+
+    #[test]
+    fn name_index_has_no_clash() {
+        let names = NAMES
+            .iter()
+            .map(|n| 1 + n.aliases.len())
+            .fold(0, |total, count| total + count);
+
+        assert_eq!(names, name_index().len());
+    }
+
+    #[test]
+    fn expand_mode_with_name() {
+        assert_eq!(Some("2560x1440@144".to_string()), expand_mode("qhd@144"));
+        assert_eq!(Some("3840x2160x30@60".to_string()), expand_mode("4kx30@60"));
+        assert_eq!(Some("1280x800@60".to_string()), expand_mode("WXGA@60"));
+        assert_eq!(Some("5120x2880".to_string()), expand_mode("5K"));
+        assert_eq!(None, expand_mode("1920x1080@60"));
+    }
+}
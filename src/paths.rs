@@ -0,0 +1,25 @@
+use std::env;
+use std::path::PathBuf;
+
+use errors::*;
+
+/// Directory where the tool keeps its own files:
+/// `~/Library/Application Support/screenresolution-rs`.
+pub fn app_dir() -> Result<PathBuf> {
+    let home = env::var_os("HOME").ok_or("Could not find the home directory")?;
+    Ok(PathBuf::from(home)
+        .join("Library")
+        .join("Application Support")
+        .join("screenresolution-rs"))
+}
+
+/// The configuration file: `$XDG_CONFIG_HOME/screenresolution-rs/config.toml` when
+/// `XDG_CONFIG_HOME` is set, `config.toml` in `app_dir()` otherwise.
+pub fn config_file() -> Result<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref config_home) if !config_home.is_empty() => Ok(PathBuf::from(config_home)
+            .join("screenresolution-rs")
+            .join("config.toml")),
+        _ => Ok(app_dir()?.join("config.toml")),
+    }
+}
@@ -0,0 +1,306 @@
+use std::f64::consts::PI;
+use std::io;
+
+use errors::*;
+use flags::ModeFlags;
+use mode::Mode;
+
+/// Logical density the user interface is designed for, at the reference viewing distance: text
+/// looks as intended at 110 PPI seen from 60 cm.
+const REFERENCE_PPI: f64 = 110.0;
+pub const REFERENCE_DISTANCE_MM: u32 = 600;
+/// Text may shrink to this fraction of its intended size for more space.
+const MIN_SPACE_TEXT_SCALE: f64 = 0.85;
+
+/// What the recommendations are computed for.
+#[derive(Debug, PartialEq)]
+pub struct Geometry {
+    /// Width and height of the image in millimeters.
+    pub size_mm: (u32, u32),
+    /// Resolution of the panel, in pixels.
+    pub native: (u64, u64),
+    pub distance_mm: u32,
+}
+
+impl Geometry {
+    /// The native resolution of a display is the one of its mode flagged native, or else the one
+    /// of its largest mode that is not scaled: HiDPI backings can be larger than the panel.
+    pub fn native_resolution<'a, I>(modes: I) -> Option<(u64, u64)>
+    where
+        I: IntoIterator<Item = &'a Mode>,
+    {
+        let modes: Vec<&Mode> = modes.into_iter().collect();
+        modes
+            .iter()
+            .find(|mode| mode.flags().contains(ModeFlags::NATIVE))
+            .or_else(|| {
+                modes
+                    .iter()
+                    .filter(|mode| mode.pixel_width == mode.width)
+                    .max_by_key(|mode| mode.pixel_width * mode.pixel_height)
+            }).map(|mode| (mode.pixel_width, mode.pixel_height))
+    }
+
+    /// Logical pixels per degree of vision for a mode of that width.
+    fn pixels_per_degree(&self, width: u64) -> f64 {
+        let ppi = width as f64 * 25.4 / f64::from(self.size_mm.0);
+        pixels_per_degree(ppi, self.distance_mm)
+    }
+}
+
+/// Pixels in one degree of vision, seen from that distance.
+fn pixels_per_degree(ppi: f64, distance_mm: u32) -> f64 {
+    let distance_inches = f64::from(distance_mm) / 25.4;
+    ppi * 2.0 * distance_inches * (PI / 360.0).tan()
+}
+
+/// How a mode would look on the display.
+pub struct Recommendation<'a> {
+    pub mode: &'a Mode,
+    /// Logical pixels per degree of vision.
+    pub pixels_per_degree: f64,
+    /// Size of text relative to the size it is designed for.
+    pub text_scale: f64,
+    /// Whether the backing resolution is the one of the panel, so that nothing is resampled.
+    pub sharp: bool,
+    pub best_readability: bool,
+    pub best_space: bool,
+}
+
+/// Rank the modes by how close their text comes to its intended size, sharp modes first among
+/// equals, then the ones with more space. Modes with the same logical resolution are only
+/// ranked once, with their sharpest backing.
+pub fn recommend<'a, I>(modes: I, geometry: &Geometry) -> Vec<Recommendation<'a>>
+where
+    I: IntoIterator<Item = &'a Mode>,
+{
+    let reference_ppd = pixels_per_degree(REFERENCE_PPI, REFERENCE_DISTANCE_MM);
+    let mut recommendations: Vec<Recommendation> = Vec::new();
+    for mode in modes {
+        let sharp = (mode.pixel_width, mode.pixel_height) == geometry.native;
+        if let Some(existing) = recommendations.iter_mut().find(|existing| {
+            (existing.mode.width, existing.mode.height) == (mode.width, mode.height)
+        }) {
+            if sharp && !existing.sharp
+                || sharp == existing.sharp && mode.pixel_width > existing.mode.pixel_width
+            {
+                existing.mode = mode;
+                existing.sharp = sharp;
+            }
+            continue;
+        }
+        let pixels_per_degree = geometry.pixels_per_degree(mode.width);
+        recommendations.push(Recommendation {
+            mode,
+            pixels_per_degree,
+            text_scale: reference_ppd / pixels_per_degree,
+            sharp,
+            best_readability: false,
+            best_space: false,
+        });
+    }
+    recommendations.sort_by(|a, b| {
+        a.text_scale
+            .ln()
+            .abs()
+            .partial_cmp(&b.text_scale.ln().abs())
+            .unwrap()
+            .then(b.sharp.cmp(&a.sharp))
+            .then((b.mode.width * b.mode.height).cmp(&(a.mode.width * a.mode.height)))
+    });
+    // Readability: the smallest text that is at least its intended size, the largest otherwise
+    let readability = recommendations
+        .iter()
+        .enumerate()
+        .filter(|&(_, recommendation)| recommendation.text_scale >= 1.0)
+        .min_by(|&(_, a), &(_, b)| a.text_scale.partial_cmp(&b.text_scale).unwrap())
+        .or_else(|| {
+            recommendations
+                .iter()
+                .enumerate()
+                .max_by(|&(_, a), &(_, b)| a.text_scale.partial_cmp(&b.text_scale).unwrap())
+        }).map(|(i, _)| i);
+    // Space: the most logical pixels with text not much smaller than intended
+    let space = recommendations
+        .iter()
+        .enumerate()
+        .filter(|&(_, recommendation)| recommendation.text_scale >= MIN_SPACE_TEXT_SCALE)
+        .max_by_key(|&(_, recommendation)| recommendation.mode.width * recommendation.mode.height)
+        .map(|(i, _)| i)
+        .or(readability);
+    if let Some(i) = readability {
+        recommendations[i].best_readability = true;
+    }
+    if let Some(i) = space {
+        recommendations[i].best_space = true;
+    }
+    recommendations
+}
+
+pub fn print_recommendations(
+    recommendations: &[Recommendation],
+    output: &mut io::Write,
+) -> Result<()> {
+    for recommendation in recommendations {
+        let mode = recommendation.mode;
+        write!(
+            output,
+            "{:>9} - pixel {:>9} - {:4.1} px/deg - text {:.2}x",
+            format!("{}x{}", mode.width, mode.height),
+            format!("{}x{}", mode.pixel_width, mode.pixel_height),
+            recommendation.pixels_per_degree,
+            recommendation.text_scale
+        )?;
+        if recommendation.sharp {
+            write!(output, " - sharp")?;
+        }
+        if recommendation.best_readability {
+            write!(output, " - best for readability")?;
+        }
+        if recommendation.best_space {
+            write!(output, " - best for space")?;
+        }
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+/// Parse dimensions written like `597x336`.
+pub fn parse_dimensions(dimensions: &str) -> Result<(u32, u32)> {
+    let mut parts = dimensions
+        .splitn(2, 'x')
+        .map(|part| part.trim().parse::<u32>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(width)), Some(Ok(height))) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("Not valid dimensions: {}", dimensions).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use refresh::RefreshRate;
+
+    fn test_mode(width: u64, height: u64, pixel_width: u64, pixel_height: u64) -> Mode {
+        Mode {
+            display: 0,
+            cgmode: None,
+            width,
+            height,
+            pixel_width,
+            pixel_height,
+            refresh_rate: RefreshRate::from_hz(60.0),
+            vrr_range: None,
+            size_mm: Some((597, 336)),
+            pixel_encoding: None,
+            encoding_capabilities: None,
+            io_flags: 0x3,
+            bit_depth: 32,
+            current: false,
+        }
+    }
+
+    /// The modes of a 27" 4K display.
+    fn test_modes() -> Vec<Mode> {
+        vec![
+            test_mode(3840, 2160, 3840, 2160),
+            test_mode(3008, 1692, 6016, 3384),
+            test_mode(2560, 1440, 5120, 2880),
+            test_mode(2560, 1440, 2560, 1440),
+            test_mode(1920, 1080, 3840, 2160),
+            test_mode(1920, 1080, 1920, 1080),
+            test_mode(1280, 720, 2560, 1440),
+        ]
+    }
+
+    fn geometry(distance_mm: u32) -> Geometry {
+        Geometry {
+            size_mm: (597, 336),
+            native: (3840, 2160),
+            distance_mm,
+        }
+    }
+
+    #[test]
+    fn native_resolution() {
+        let mut modes = test_modes();
+
+        // 6016x3384 is only the backing of a HiDPI mode
+        assert_eq!(
+            Some((3840, 2160)),
+            Geometry::native_resolution(modes.iter())
+        );
+        modes[3].io_flags |= ModeFlags::NATIVE.bits();
+        assert_eq!(
+            Some((2560, 1440)),
+            Geometry::native_resolution(modes.iter())
+        );
+        assert_eq!(None, Geometry::native_resolution(modes[1..3].iter()));
+    }
+
+    #[test]
+    fn recommend_at_reference_distance() {
+        let modes = test_modes();
+        let recommendations = recommend(modes.iter(), &geometry(REFERENCE_DISTANCE_MM));
+        let resolutions: Vec<(u64, u64)> = recommendations
+            .iter()
+            .map(|recommendation| (recommendation.mode.width, recommendation.mode.height))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (2560, 1440),
+                (3008, 1692),
+                (1920, 1080),
+                (3840, 2160),
+                (1280, 720),
+            ],
+            resolutions
+        );
+        // The HiDPI backing is kept for 2560x1440, the sharp one for 1920x1080
+        assert_eq!(5120, recommendations[0].mode.pixel_width);
+        assert_eq!(true, recommendations[2].sharp);
+        assert_eq!(true, recommendations[0].best_readability);
+        assert_eq!(true, recommendations[1].best_space);
+    }
+
+    #[test]
+    fn recommend_from_further_away() {
+        let modes = test_modes();
+        let recommendations = recommend(modes.iter(), &geometry(900));
+
+        assert_eq!(
+            (1920, 1080),
+            (
+                recommendations[0].mode.width,
+                recommendations[0].mode.height
+            )
+        );
+        assert_eq!(true, recommendations[0].best_space);
+        // Only 1280x720 keeps text at its intended size
+        assert_eq!(true, recommendations[1].best_readability);
+    }
+
+    #[test]
+    fn print_recommendation() {
+        let modes = vec![test_mode(1920, 1080, 3840, 2160)];
+        let mut vec = Vec::<u8>::new();
+
+        print_recommendations(
+            &recommend(modes.iter(), &geometry(REFERENCE_DISTANCE_MM)),
+            &mut vec,
+        ).expect("Error while testing print_recommendations");
+
+        assert_eq!(
+            "1920x1080 - pixel 3840x2160 - 33.7 px/deg - text 1.35x - sharp - best for readability - best for space\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_valid_dimensions() {
+        assert_eq!((597, 336), parse_dimensions("597x336").unwrap());
+        assert_eq!(true, parse_dimensions("597").is_err());
+        assert_eq!(true, parse_dimensions("0x336").is_err());
+    }
+}
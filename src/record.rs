@@ -0,0 +1,89 @@
+use libc;
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::slice;
+
+use errors::*;
+use paths;
+
+/// A small file in `paths::app_dir` telling about an operation in progress: a line of tab
+/// separated fields per record, the first one being the process doing the operation. Finding a
+/// record of a process that is gone means that the operation was interrupted.
+pub trait Record: Sized {
+    /// Name of the file in `paths::app_dir`.
+    const FILE_NAME: &'static str;
+    /// What the record is called in error messages.
+    const NAME: &'static str;
+    /// Number of fields after the pid.
+    const FIELDS: usize;
+
+    fn pid(&self) -> u32;
+    fn fields(&self) -> Vec<&str>;
+    fn from_fields(pid: u32, fields: &[&str]) -> Self;
+
+    fn path() -> Result<PathBuf> {
+        Ok(paths::app_dir()?.join(Self::FILE_NAME))
+    }
+
+    fn load(path: &Path) -> Result<Option<Self>> {
+        Ok(Self::load_all(path)?.into_iter().next())
+    }
+
+    fn load_all(path: &Path) -> Result<Vec<Self>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .chain_err(|| format!("Could not read {}", Self::NAME))?;
+        let mut records = Vec::new();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.trim().split('\t').collect();
+            if fields.len() != Self::FIELDS + 1 {
+                return Err(format!("Not a valid {}: {}", Self::NAME, line).into());
+            }
+            let pid = fields[0]
+                .parse()
+                .chain_err(|| format!("Not a valid {} pid: {}", Self::NAME, fields[0]))?;
+            records.push(Self::from_fields(pid, &fields[1..]));
+        }
+        Ok(records)
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        Self::save_all(slice::from_ref(self), path)
+    }
+
+    fn save_all(records: &[Self], path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .chain_err(|| format!("Could not create {} directory", Self::NAME))?;
+        }
+        let mut file =
+            File::create(path).chain_err(|| format!("Could not write {}", Self::NAME))?;
+        for record in records {
+            writeln!(file, "{}\t{}", record.pid(), record.fields().join("\t"))
+                .chain_err(|| format!("Could not write {}", Self::NAME))?;
+        }
+        file.sync_all()
+            .chain_err(|| format!("Could not write {}", Self::NAME))?;
+        Ok(())
+    }
+
+    fn clear(path: &Path) -> Result<()> {
+        if path.exists() {
+            fs::remove_file(path).chain_err(|| format!("Could not remove {}", Self::NAME))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the process doing the operation is gone. A process that exists but cannot be
+    /// signalled, as when it belongs to another user, is alive.
+    fn is_stale(&self) -> bool {
+        let result = unsafe { libc::kill(self.pid() as libc::pid_t, 0) };
+        result != 0 && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::str::FromStr;
+
+use errors::*;
+
+/// Refresh rate of a mode. Rates are kept in millihertz so that they can be compared, sorted and
+/// hashed without the surprises of floating point numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RefreshRate {
+    /// The display does not tell, as built-in panels that report 0.
+    Unknown,
+    Exact(u32),
+    /// The display refreshes anywhere between `min` and `max`.
+    Variable {
+        min: u32,
+        max: u32,
+    },
+}
+
+impl RefreshRate {
+    /// The rate in hertz as reported by CoreGraphics, where 0 means unknown.
+    pub fn from_hz(hz: f64) -> RefreshRate {
+        if hz.is_finite() && hz > 0.0 {
+            RefreshRate::Exact((hz * 1000.0).round() as u32)
+        } else {
+            RefreshRate::Unknown
+        }
+    }
+
+    /// Whether a mode with this rate will do for the wanted rate. Unless `exact`, rates that are
+    /// within 0.2% of each other match, so that 60 matches the 59.94 of NTSC-style timings.
+    pub fn matches(&self, wanted: &RefreshRate, exact: bool) -> bool {
+        let close = |rate: u32, wanted: u32| {
+            if exact {
+                rate == wanted
+            } else {
+                let difference = if rate > wanted {
+                    rate - wanted
+                } else {
+                    wanted - rate
+                };
+                difference <= rate.max(wanted) / 500
+            }
+        };
+        match (*self, *wanted) {
+            (RefreshRate::Exact(rate), RefreshRate::Exact(wanted)) => close(rate, wanted),
+            (RefreshRate::Variable { min, max }, RefreshRate::Exact(wanted)) => {
+                (min <= wanted && wanted <= max) || close(min, wanted) || close(max, wanted)
+            }
+            (rate, wanted) => rate == wanted,
+        }
+    }
+}
+
+fn write_millihertz(f: &mut fmt::Formatter, millihertz: u32) -> fmt::Result {
+    if millihertz % 1000 == 0 {
+        write!(f, "{}", millihertz / 1000)
+    } else {
+        let fraction = format!("{:03}", millihertz % 1000);
+        write!(
+            f,
+            "{}.{}",
+            millihertz / 1000,
+            fraction.trim_end_matches('0')
+        )
+    }
+}
+
+/// Rates are written as in mode strings: `0` when unknown, `59.94`, or `48-120` when variable.
+impl fmt::Display for RefreshRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RefreshRate::Unknown => write!(f, "0"),
+            RefreshRate::Exact(millihertz) => write_millihertz(f, millihertz),
+            RefreshRate::Variable { min, max } => {
+                write_millihertz(f, min)?;
+                write!(f, "-")?;
+                write_millihertz(f, max)
+            }
+        }
+    }
+}
+
+impl FromStr for RefreshRate {
+    type Err = Error;
+
+    fn from_str(rate: &str) -> Result<RefreshRate> {
+        let hz = |hz: &str| {
+            hz.parse::<f64>()
+                .chain_err(|| format!("Not a valid refresh rate: {}", rate))
+                .map(RefreshRate::from_hz)
+        };
+        match rate.find('-') {
+            Some(position) => match (hz(&rate[..position])?, hz(&rate[position + 1..])?) {
+                (RefreshRate::Exact(min), RefreshRate::Exact(max)) if min <= max => {
+                    Ok(RefreshRate::Variable { min, max })
+                }
+                _ => Err(format!("Not a valid refresh rate range: {}", rate).into()),
+            },
+            None => hz(rate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_rates() {
+        assert_eq!("0", RefreshRate::from_hz(0.0).to_string());
+        assert_eq!("60", RefreshRate::from_hz(60.0).to_string());
+        assert_eq!("59.94", RefreshRate::from_hz(59.94).to_string());
+        assert_eq!("21.2", RefreshRate::from_hz(21.2).to_string());
+        assert_eq!(
+            "48-120",
+            RefreshRate::Variable {
+                min: 48000,
+                max: 120000,
+            }.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_rates() {
+        assert_eq!(RefreshRate::Unknown, "0".parse::<RefreshRate>().unwrap());
+        assert_eq!(
+            RefreshRate::Exact(59940),
+            "59.94".parse::<RefreshRate>().unwrap()
+        );
+        assert_eq!(
+            RefreshRate::Variable {
+                min: 48000,
+                max: 120000,
+            },
+            "48-120".parse::<RefreshRate>().unwrap()
+        );
+        assert_eq!(true, "120-48".parse::<RefreshRate>().is_err());
+        assert_eq!(true, "fast".parse::<RefreshRate>().is_err());
+    }
+
+    #[test]
+    fn matches_with_tolerance() {
+        let ntsc = RefreshRate::from_hz(59.94);
+        let sixty = RefreshRate::from_hz(60.0);
+
+        assert_eq!(true, ntsc.matches(&sixty, false));
+        assert_eq!(false, ntsc.matches(&sixty, true));
+        assert_eq!(false, RefreshRate::from_hz(59.0).matches(&sixty, false));
+        assert_eq!(false, RefreshRate::Unknown.matches(&sixty, false));
+        assert_eq!(
+            true,
+            RefreshRate::Unknown.matches(&RefreshRate::Unknown, true)
+        );
+    }
+
+    #[test]
+    fn matches_variable() {
+        let variable = RefreshRate::Variable {
+            min: 48000,
+            max: 120000,
+        };
+
+        assert_eq!(true, variable.matches(&RefreshRate::from_hz(100.0), true));
+        assert_eq!(false, variable.matches(&RefreshRate::from_hz(144.0), false));
+    }
+
+    #[test]
+    fn ordering() {
+        let mut rates = vec![
+            RefreshRate::from_hz(60.0),
+            RefreshRate::Unknown,
+            RefreshRate::from_hz(59.94),
+        ];
+        rates.sort();
+
+        assert_eq!(
+            vec![
+                RefreshRate::Unknown,
+                RefreshRate::from_hz(59.94),
+                RefreshRate::from_hz(60.0),
+            ],
+            rates
+        );
+    }
+}
@@ -0,0 +1,572 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use errors::*;
+
+/// Pixels are grouped in cells of 8 for the horizontal timings of CVT and GTF.
+const CELL_GRANULARITY: u32 = 8;
+/// Minimum time for the vertical sync and back porch in CVT and GTF, in microseconds.
+const MIN_VSYNC_BACK_PORCH: f64 = 550.0;
+/// Minimum vertical front porch in lines, for CVT and CVT reduced blanking.
+const MIN_V_FRONT_PORCH: u32 = 3;
+/// Minimum vertical back porch in lines, for all CVT variants.
+const MIN_V_BACK_PORCH: u32 = 6;
+/// Horizontal sync width, as a percentage of the total line, in CVT and GTF.
+const H_SYNC_PERCENTAGE: f64 = 8.0;
+/// Blanking formula gradient and offset of CVT and GTF, once scaled (C' and M' in the standards).
+const BLANKING_C_PRIME: f64 = 30.0;
+const BLANKING_M_PRIME: f64 = 300.0;
+/// Minimum vertical blanking time of the reduced blanking variants, in microseconds.
+const RB_MIN_V_BLANK: f64 = 460.0;
+
+/// A VESA standard to compute timings with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Standard {
+    /// Coordinated Video Timings, for CRTs and displays that need their blanking.
+    Cvt,
+    /// CVT with reduced blanking, for digital displays.
+    CvtReducedBlanking,
+    /// CVT with reduced blanking version 2, with a finer pixel clock and shorter blanking.
+    CvtReducedBlankingV2,
+    /// Generalized Timing Formula, which CVT replaces.
+    Gtf,
+}
+
+impl Standard {
+    /// The name used on the command line.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Standard::Cvt => "cvt",
+            Standard::CvtReducedBlanking => "cvt-rb",
+            Standard::CvtReducedBlankingV2 => "cvt-rb2",
+            Standard::Gtf => "gtf",
+        }
+    }
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Standard::Cvt => "CVT",
+                Standard::CvtReducedBlanking => "CVT-RB",
+                Standard::CvtReducedBlankingV2 => "CVT-RBv2",
+                Standard::Gtf => "GTF",
+            }
+        )
+    }
+}
+
+impl FromStr for Standard {
+    type Err = Error;
+
+    fn from_str(standard: &str) -> Result<Standard> {
+        [
+            Standard::Cvt,
+            Standard::CvtReducedBlanking,
+            Standard::CvtReducedBlankingV2,
+            Standard::Gtf,
+        ]
+        .iter()
+        .find(|known| known.key().eq_ignore_ascii_case(standard))
+        .cloned()
+        .ok_or_else(|| format!("Not a known timing standard: {}", standard).into())
+    }
+}
+
+/// Timings of a mode, as given to a display or in an xrandr modeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timings {
+    pub standard: Standard,
+    /// The refresh rate the timings were asked for, in hertz.
+    pub wanted_refresh: f64,
+    /// Pixel clock in kilohertz.
+    pub pixel_clock: u32,
+    pub h_display: u32,
+    pub h_sync_start: u32,
+    pub h_sync_end: u32,
+    pub h_total: u32,
+    pub v_display: u32,
+    pub v_sync_start: u32,
+    pub v_sync_end: u32,
+    pub v_total: u32,
+    pub h_sync_positive: bool,
+    pub v_sync_positive: bool,
+}
+
+/// The vertical sync width of CVT, which tells the aspect ratio of the mode to the display.
+fn cvt_v_sync(width: u32, height: u32) -> u32 {
+    if height % 3 == 0 && height * 4 / 3 == width {
+        4
+    } else if height % 9 == 0 && height * 16 / 9 == width {
+        5
+    } else if height % 10 == 0 && height * 16 / 10 == width {
+        6
+    } else if (height % 4 == 0 && height * 5 / 4 == width)
+        || (height % 9 == 0 && height * 15 / 9 == width)
+    {
+        7
+    } else {
+        10
+    }
+}
+
+impl Timings {
+    /// Compute the timings of a mode with the given standard.
+    pub fn compute(standard: Standard, width: u32, height: u32, refresh: f64) -> Result<Timings> {
+        // Smaller sizes round to no cell at all
+        if width < CELL_GRANULARITY || height < CELL_GRANULARITY {
+            return Err(format!("Not a valid resolution: {}x{}", width, height).into());
+        }
+        let min_blank = match standard {
+            Standard::Cvt | Standard::Gtf => MIN_VSYNC_BACK_PORCH,
+            Standard::CvtReducedBlanking | Standard::CvtReducedBlankingV2 => RB_MIN_V_BLANK,
+        };
+        // The frame must last longer than its vertical blanking
+        if !refresh.is_finite() || refresh <= 0.0 || 1_000_000.0 / refresh <= min_blank {
+            return Err(format!("Not a valid refresh rate for {}: {}", standard, refresh).into());
+        }
+        let timings = match standard {
+            Standard::Cvt => Timings::cvt(width, height, refresh),
+            Standard::CvtReducedBlanking => Timings::cvt_reduced_blanking(width, height, refresh),
+            Standard::CvtReducedBlankingV2 => {
+                Timings::cvt_reduced_blanking_v2(width, height, refresh)
+            }
+            Standard::Gtf => Timings::gtf(width, height, refresh)?,
+        };
+        if timings.pixel_clock == 0 || !timings.is_ordered() {
+            return Err(format!(
+                "No {} timings for {}x{}@{}",
+                standard, width, height, refresh
+            ).into());
+        }
+        Ok(timings)
+    }
+
+    /// Whether the sync pulses come after the active area and end within the total, so that no
+    /// porch is negative.
+    fn is_ordered(&self) -> bool {
+        self.h_display <= self.h_sync_start
+            && self.h_sync_start <= self.h_sync_end
+            && self.h_sync_end <= self.h_total
+            && self.v_display <= self.v_sync_start
+            && self.v_sync_start <= self.v_sync_end
+            && self.v_sync_end <= self.v_total
+    }
+
+    fn cvt(width: u32, height: u32, refresh: f64) -> Timings {
+        let h_display = width - width % CELL_GRANULARITY;
+        let v_sync = cvt_v_sync(width, height);
+        // Line period in microseconds
+        let h_period =
+            (1_000_000.0 / refresh - MIN_VSYNC_BACK_PORCH) / f64::from(height + MIN_V_FRONT_PORCH);
+        let v_sync_back_porch =
+            ((MIN_VSYNC_BACK_PORCH / h_period) as u32 + 1).max(v_sync + MIN_V_BACK_PORCH);
+        let v_total = height + v_sync_back_porch + MIN_V_FRONT_PORCH;
+        let blank_percentage = (BLANKING_C_PRIME - BLANKING_M_PRIME * h_period / 1000.0).max(20.0);
+        let h_blank = (f64::from(h_display) * blank_percentage / (100.0 - blank_percentage)) as u32;
+        let h_blank = h_blank - h_blank % (2 * CELL_GRANULARITY);
+        let h_total = h_display + h_blank;
+        let pixel_clock = (f64::from(h_total) * 1000.0 / h_period) as u32;
+        let h_sync = (f64::from(h_total) * H_SYNC_PERCENTAGE / 100.0) as u32;
+        let h_sync = h_sync - h_sync % CELL_GRANULARITY;
+        let h_sync_end = h_display + h_blank / 2;
+        Timings {
+            standard: Standard::Cvt,
+            wanted_refresh: refresh,
+            pixel_clock: pixel_clock - pixel_clock % 250,
+            h_display,
+            h_sync_start: h_sync_end - h_sync,
+            h_sync_end,
+            h_total,
+            v_display: height,
+            v_sync_start: height + MIN_V_FRONT_PORCH,
+            v_sync_end: height + MIN_V_FRONT_PORCH + v_sync,
+            v_total,
+            h_sync_positive: false,
+            v_sync_positive: true,
+        }
+    }
+
+    fn cvt_reduced_blanking(width: u32, height: u32, refresh: f64) -> Timings {
+        const H_BLANK: u32 = 160;
+        const H_SYNC: u32 = 32;
+        let h_display = width - width % CELL_GRANULARITY;
+        let v_sync = cvt_v_sync(width, height);
+        let h_period = (1_000_000.0 / refresh - RB_MIN_V_BLANK) / f64::from(height);
+        let v_blank = ((RB_MIN_V_BLANK / h_period) as u32 + 1)
+            .max(MIN_V_FRONT_PORCH + v_sync + MIN_V_BACK_PORCH);
+        let v_total = height + v_blank;
+        let h_total = h_display + H_BLANK;
+        let pixel_clock = (f64::from(h_total * v_total) * refresh / 1000.0) as u32;
+        let h_sync_end = h_display + H_BLANK / 2;
+        Timings {
+            standard: Standard::CvtReducedBlanking,
+            wanted_refresh: refresh,
+            pixel_clock: pixel_clock - pixel_clock % 250,
+            h_display,
+            h_sync_start: h_sync_end - H_SYNC,
+            h_sync_end,
+            h_total,
+            v_display: height,
+            v_sync_start: height + MIN_V_FRONT_PORCH,
+            v_sync_end: height + MIN_V_FRONT_PORCH + v_sync,
+            v_total,
+            h_sync_positive: true,
+            v_sync_positive: false,
+        }
+    }
+
+    fn cvt_reduced_blanking_v2(width: u32, height: u32, refresh: f64) -> Timings {
+        const H_BLANK: u32 = 80;
+        const H_SYNC: u32 = 32;
+        const H_FRONT_PORCH: u32 = 8;
+        const V_SYNC: u32 = 8;
+        const MIN_V_FRONT_PORCH_V2: u32 = 1;
+        let h_period = (1_000_000.0 / refresh - RB_MIN_V_BLANK) / f64::from(height);
+        let v_blank = ((RB_MIN_V_BLANK / h_period) as u32 + 1)
+            .max(MIN_V_FRONT_PORCH_V2 + V_SYNC + MIN_V_BACK_PORCH);
+        let v_total = height + v_blank;
+        let h_total = width + H_BLANK;
+        // Version 2 is not bound to cells and has a 1 kHz clock step
+        let pixel_clock = (f64::from(h_total * v_total) * refresh / 1000.0) as u32;
+        let v_sync_start = height + v_blank - V_SYNC - MIN_V_BACK_PORCH;
+        Timings {
+            standard: Standard::CvtReducedBlankingV2,
+            wanted_refresh: refresh,
+            pixel_clock,
+            h_display: width,
+            h_sync_start: width + H_FRONT_PORCH,
+            h_sync_end: width + H_FRONT_PORCH + H_SYNC,
+            h_total,
+            v_display: height,
+            v_sync_start,
+            v_sync_end: v_sync_start + V_SYNC,
+            v_total,
+            h_sync_positive: true,
+            v_sync_positive: false,
+        }
+    }
+
+    fn gtf(width: u32, height: u32, refresh: f64) -> Result<Timings> {
+        const MIN_PORCH: u32 = 1;
+        const V_SYNC: u32 = 3;
+        let h_display =
+            (f64::from(width) / f64::from(CELL_GRANULARITY)).round() as u32 * CELL_GRANULARITY;
+        let h_period_estimate =
+            (1_000_000.0 / refresh - MIN_VSYNC_BACK_PORCH) / f64::from(height + MIN_PORCH);
+        let v_sync_back_porch = (MIN_VSYNC_BACK_PORCH / h_period_estimate).round() as u32;
+        let v_total = height + v_sync_back_porch + MIN_PORCH;
+        // Correct the line period for the rate the estimate would give
+        let refresh_estimate = 1_000_000.0 / h_period_estimate / f64::from(v_total);
+        let h_period = h_period_estimate * refresh_estimate / refresh;
+        let duty_cycle = BLANKING_C_PRIME - BLANKING_M_PRIME * h_period / 1000.0;
+        // Lines this long leave no time for the horizontal blanking
+        if duty_cycle <= 0.0 {
+            return Err(format!(
+                "Not a valid refresh rate for {} at {}x{}: {}",
+                Standard::Gtf,
+                width,
+                height,
+                refresh
+            ).into());
+        }
+        let h_blank = (f64::from(h_display) * duty_cycle
+            / (100.0 - duty_cycle)
+            / f64::from(2 * CELL_GRANULARITY))
+        .round() as u32
+            * 2
+            * CELL_GRANULARITY;
+        let h_total = h_display + h_blank;
+        let h_sync = (H_SYNC_PERCENTAGE / 100.0 * f64::from(h_total) / f64::from(CELL_GRANULARITY))
+            .round() as u32
+            * CELL_GRANULARITY;
+        let h_sync_start = h_display + h_blank / 2 - h_sync;
+        Ok(Timings {
+            standard: Standard::Gtf,
+            wanted_refresh: refresh,
+            pixel_clock: (f64::from(h_total) * 1000.0 / h_period).round() as u32,
+            h_display,
+            h_sync_start,
+            h_sync_end: h_sync_start + h_sync,
+            h_total,
+            v_display: height,
+            v_sync_start: height + MIN_PORCH,
+            v_sync_end: height + MIN_PORCH + V_SYNC,
+            v_total,
+            h_sync_positive: false,
+            v_sync_positive: true,
+        })
+    }
+
+    /// The refresh rate the timings actually give, in hertz.
+    pub fn refresh(&self) -> f64 {
+        f64::from(self.pixel_clock) * 1000.0 / f64::from(self.h_total * self.v_total)
+    }
+
+    /// The line rate in kilohertz.
+    pub fn h_frequency(&self) -> f64 {
+        f64::from(self.pixel_clock) / f64::from(self.h_total)
+    }
+
+    pub fn h_front_porch(&self) -> u32 {
+        self.h_sync_start - self.h_display
+    }
+
+    pub fn h_sync_width(&self) -> u32 {
+        self.h_sync_end - self.h_sync_start
+    }
+
+    pub fn h_back_porch(&self) -> u32 {
+        self.h_total - self.h_sync_end
+    }
+
+    pub fn v_front_porch(&self) -> u32 {
+        self.v_sync_start - self.v_display
+    }
+
+    pub fn v_sync_width(&self) -> u32 {
+        self.v_sync_end - self.v_sync_start
+    }
+
+    pub fn v_back_porch(&self) -> u32 {
+        self.v_total - self.v_sync_end
+    }
+
+    /// The name of the modeline, as cvt and gtf name them: `1920x1080_60.00`, with an `R` after
+    /// the resolution for reduced blanking.
+    pub fn name(&self) -> String {
+        let reduced = match self.standard {
+            Standard::Cvt | Standard::Gtf => "",
+            Standard::CvtReducedBlanking => "R",
+            Standard::CvtReducedBlankingV2 => "R2",
+        };
+        format!(
+            "{}x{}{}_{:.2}",
+            self.h_display, self.v_display, reduced, self.wanted_refresh
+        )
+    }
+
+    /// Print the modeline, after the details of the timings as comments.
+    pub fn print_modeline(&self, output: &mut io::Write) -> Result<()> {
+        writeln!(
+            output,
+            "# {}x{} {:.2} Hz ({}) hsync: {:.2} kHz; pclk: {:.2} MHz",
+            self.h_display,
+            self.v_display,
+            self.refresh(),
+            self.standard,
+            self.h_frequency(),
+            f64::from(self.pixel_clock) / 1000.0
+        )?;
+        writeln!(
+            output,
+            "# horizontal: front porch {}, sync {}, back porch {}",
+            self.h_front_porch(),
+            self.h_sync_width(),
+            self.h_back_porch()
+        )?;
+        writeln!(
+            output,
+            "# vertical: front porch {}, sync {}, back porch {}",
+            self.v_front_porch(),
+            self.v_sync_width(),
+            self.v_back_porch()
+        )?;
+        writeln!(output, "{}", self)?;
+        Ok(())
+    }
+}
+
+/// The xrandr modeline of the timings. The pixel clock has three decimals only when the 1 kHz
+/// step of CVT-RBv2 needs them.
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let clock = if self.pixel_clock % 10 == 0 {
+            format!("{:.2}", f64::from(self.pixel_clock) / 1000.0)
+        } else {
+            format!("{:.3}", f64::from(self.pixel_clock) / 1000.0)
+        };
+        write!(
+            f,
+            "Modeline \"{}\"  {}  {} {} {} {}  {} {} {} {} {}hsync {}vsync",
+            self.name(),
+            clock,
+            self.h_display,
+            self.h_sync_start,
+            self.h_sync_end,
+            self.h_total,
+            self.v_display,
+            self.v_sync_start,
+            self.v_sync_end,
+            self.v_total,
+            if self.h_sync_positive { "+" } else { "-" },
+            if self.v_sync_positive { "+" } else { "-" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values are those of the VESA CVT 1.2 and GTF spreadsheets, as also given by the
+    // cvt and gtf tools.
+
+    #[test]
+    fn cvt_reference_modes() {
+        assert_eq!(
+            "Modeline \"1920x1080_60.00\"  173.00  1920 2048 2248 2576  1080 1083 1088 1120 -hsync +vsync",
+            Timings::compute(Standard::Cvt, 1920, 1080, 60.0)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "Modeline \"1024x768_60.00\"  63.50  1024 1072 1176 1328  768 771 775 798 -hsync +vsync",
+            Timings::compute(Standard::Cvt, 1024, 768, 60.0)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "Modeline \"1280x800_60.00\"  83.50  1280 1352 1480 1680  800 803 809 831 -hsync +vsync",
+            Timings::compute(Standard::Cvt, 1280, 800, 60.0)
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn cvt_reduced_blanking_reference_modes() {
+        assert_eq!(
+            "Modeline \"1920x1080R_60.00\"  138.50  1920 1968 2000 2080  1080 1083 1088 1111 +hsync -vsync",
+            Timings::compute(Standard::CvtReducedBlanking, 1920, 1080, 60.0)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "Modeline \"2560x1440R_60.00\"  241.50  2560 2608 2640 2720  1440 1443 1448 1481 +hsync -vsync",
+            Timings::compute(Standard::CvtReducedBlanking, 2560, 1440, 60.0)
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn cvt_reduced_blanking_v2_reference_mode() {
+        let timings = Timings::compute(Standard::CvtReducedBlankingV2, 1920, 1080, 60.0).unwrap();
+
+        assert_eq!(133320, timings.pixel_clock);
+        assert_eq!(
+            (8, 32, 40),
+            (
+                timings.h_front_porch(),
+                timings.h_sync_width(),
+                timings.h_back_porch()
+            )
+        );
+        assert_eq!(
+            (17, 8, 6),
+            (
+                timings.v_front_porch(),
+                timings.v_sync_width(),
+                timings.v_back_porch()
+            )
+        );
+        assert_eq!(1111, timings.v_total);
+    }
+
+    #[test]
+    fn gtf_reference_mode() {
+        let timings = Timings::compute(Standard::Gtf, 1920, 1080, 60.0).unwrap();
+
+        assert_eq!(
+            "172.80",
+            format!("{:.2}", f64::from(timings.pixel_clock) / 1000.0)
+        );
+        assert_eq!(
+            (2040, 2248, 2576),
+            (timings.h_sync_start, timings.h_sync_end, timings.h_total)
+        );
+        assert_eq!(
+            (1081, 1084, 1118),
+            (timings.v_sync_start, timings.v_sync_end, timings.v_total)
+        );
+    }
+
+    #[test]
+    fn timings_without_blanking() {
+        // GTF leaves no horizontal blanking at low refresh rates
+        assert_eq!(
+            true,
+            Timings::compute(Standard::Gtf, 1920, 1080, 5.0).is_err()
+        );
+        assert_eq!(
+            true,
+            Timings::compute(Standard::Gtf, 640, 480, 1.0).is_err()
+        );
+        // Frames of so few lines have lines too long for any blanking
+        assert_eq!(true, Timings::compute(Standard::Gtf, 8, 8, 60.0).is_err());
+        // Sizes under a cell round to nothing
+        assert_eq!(true, Timings::compute(Standard::Gtf, 4, 4, 60.0).is_err());
+        assert_eq!(true, Timings::compute(Standard::Cvt, 7, 16, 60.0).is_err());
+        // The pixel clock of tiny modes rounds to 0
+        assert_eq!(
+            true,
+            Timings::compute(Standard::CvtReducedBlanking, 8, 8, 1.0).is_err()
+        );
+    }
+
+    #[test]
+    fn refresh_close_to_wanted() {
+        let timings = Timings::compute(Standard::Cvt, 1920, 1080, 60.0).unwrap();
+
+        assert_eq!(true, (timings.refresh() - 59.96).abs() < 0.01);
+        assert_eq!(true, (timings.h_frequency() - 67.16).abs() < 0.01);
+    }
+
+    #[test]
+    fn print_modeline() {
+        let mut vec = Vec::<u8>::new();
+
+        Timings::compute(Standard::CvtReducedBlankingV2, 1920, 1080, 60.0)
+            .unwrap()
+            .print_modeline(&mut vec)
+            .expect("Error while testing print_modeline");
+
+        assert_eq!(
+            "# 1920x1080 60.00 Hz (CVT-RBv2) hsync: 66.66 kHz; pclk: 133.32 MHz\n\
+             # horizontal: front porch 8, sync 32, back porch 40\n\
+             # vertical: front porch 17, sync 8, back porch 6\n\
+             Modeline \"1920x1080R2_60.00\"  133.32  1920 1928 1960 2000  1080 1097 1105 1111 +hsync -vsync\n",
+            String::from_utf8(vec).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_requests() {
+        assert_eq!(
+            true,
+            Timings::compute(Standard::Cvt, 0, 1080, 60.0).is_err()
+        );
+        assert_eq!(
+            true,
+            Timings::compute(Standard::Gtf, 1920, 1080, 0.0).is_err()
+        );
+        assert_eq!(
+            true,
+            Timings::compute(Standard::CvtReducedBlanking, 1920, 1080, 5000.0).is_err()
+        );
+    }
+
+    #[test]
+    fn parse_standard() {
+        assert_eq!(
+            Standard::CvtReducedBlankingV2,
+            "CVT-RB2".parse::<Standard>().unwrap()
+        );
+        assert_eq!(true, "dmt".parse::<Standard>().is_err());
+    }
+}